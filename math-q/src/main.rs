@@ -44,61 +44,22 @@ fn main() {
     println!("Created network function {}", term.count_nodes());
     let var1 = math_type::variable("w^(1)_(00)");
     let var2 = math_type::variable("w^(1)_(10)");
-    let deriv = term.get_derivative(&var1).get_derivative(&var2);
+
+    //Both weights only enter the pre-activation sum of the first neuron in layer 1, with the inputs x_0 and x_1 as factors.
+    //Deriving twice with respect to that sum gives the delta terms f'(...) and f''(...) directly from the chain rule
+    let pre_activation = layers[1][0].parameter[0].clone();
+    let deriv = math_type::product(vec![
+        layers[0][0].clone(),
+        layers[0][1].clone(),
+        term.get_derivative_wrt(&pre_activation).get_derivative_wrt(&pre_activation),
+    ]);
     
     println!("Finished derivative {}", deriv.count_nodes());
     // vec.push(deriv.clone());
 
     // println!("{}", deriv.get_string());
-    
-    let mut sorted = deriv.clone();
-    sorted.sort();
-    
-    let mut prev = sorted.clone();
-
-    for layer_index in (1..layer_count.len()).rev() {
-        for node_index in 0..layer_count[layer_index] {
-            let mut val = layers[layer_index][node_index].clone();
-            val.sort();
-
-            let deri = math_type::function("f'", val.parameter.clone());
-            prev = prev.replace(&deri, &math_type::variable(&format!("delta^({})_({})", layer_index, node_index)));
-        }
-
-        println!("Finished delta replacement {}", layer_index);
-    }
-
-    println!("Finished delta replacement");
 
-    prev.sort();
-    for layer_index in (1..layer_count.len()).rev() {
-        for node_index in 0..layer_count[layer_index] {
-            let mut val = layers[layer_index][node_index].clone();
-            val.sort();
-
-            let deri = math_type::function("f''", val.parameter.clone());
-            prev = prev.replace(&deri, &math_type::variable(&format!("Delta^({})_({})", layer_index, node_index)));
-            
-            // vec.push(prev.clone());
-        }
-    }
-
-    println!("Finished Delta replacement");
-
-    prev.sort();
-    for layer_index in (1..layer_count.len()).rev() {
-        for node_index in 0..layer_count[layer_index] {
-            layers[layer_index][node_index].sort();
-
-            prev = prev.replace(&layers[layer_index][node_index], &math_type::variable(&format!("n^({})_({})", layer_index, node_index)));
-            
-            // vec.push(prev.clone());
-        }
-    }
-
-    println!("Finished n replacement");
-
-    prev = prev.simplify();
+    let prev = deriv.simplify();
     
     println!("{}", prev.count_nodes());
 
@@ -130,7 +91,7 @@ fn main() {
         vec = vec![math_type::sum(vec![vec.last().unwrap().clone()])];
     }
 
-    generate_graphs(vec, layer_count, var1, var2);
+    generate_graphs(vec, &layers, var1, var2);
 }
 
 fn generate_sub_paths(layer_count: Vec<usize>) {
@@ -196,7 +157,9 @@ fn generate_sub_paths(layer_count: Vec<usize>) {
     }
 }
 
-fn generate_graphs(vec: Vec<MathType>, layer_count: Vec<usize>, var1: MathType, var2: MathType) {
+fn generate_graphs(vec: Vec<MathType>, layers: &[Vec<MathType>], var1: MathType, var2: MathType) {
+    let layer_count = layers.iter().map(|x| x.len()).collect::<Vec<usize>>();
+
     for first_sum in vec.last().unwrap().parameter.iter() {
     let mut used_weights = Vec::new();
     let mut used_n = Vec::new();
@@ -209,20 +172,32 @@ fn generate_graphs(vec: Vec<MathType>, layer_count: Vec<usize>, var1: MathType,
     for m in &first_sum.parameter {
         // println!("Parameter: {}", m.get_string());
 
-        if m.get_string().contains("w") {
-            used_weights.push(weight_index_from_string(&m.get_string()));
-        }
-        if m.get_string().contains("n") {
-            used_n.push(index_from_string(&m.get_string()));
-        }
-        if m.get_string().contains("x") {
-            used_n.push((0, m.get_string().split("_").last().unwrap().parse().unwrap()));
-        }
-        if m.get_string().contains("delta") {
-            used_delta.push(index_from_string(&m.get_string()));
+        //pow(f'(...), 2) marks the same node as f'(...)
+        let m = if m.type_name == MathTypeName::Power { &m.parameter[0] } else { m };
+        let MathTypeName::Function(name) = &m.type_name else {
+            continue;
+        };
+
+        if m.is_variable() {
+            if name.starts_with("w^") {
+                used_weights.push(weight_index_from_string(name));
+            }
+            if name.starts_with("x_") {
+                used_n.push((0, name.split("_").last().unwrap().parse().unwrap()));
+            }
+            continue;
         }
-        if m.get_string().contains("Delta") {
-            used_Delta.push(index_from_string(&m.get_string()));
+
+        //The neuron of f, f' and f'' is the one whose pre-activation sum they are called with
+        let Some(index) = neuron_index(layers, &m.parameter) else {
+            continue;
+        };
+
+        match name.as_str() {
+            "f" => used_n.push(index),
+            "f'" => used_delta.push(index),
+            "f''" => used_Delta.push(index),
+            _ => {},
         }
 
         fn weight_index_from_string(s: &str) -> (usize, usize, usize) {
@@ -237,11 +212,10 @@ fn generate_graphs(vec: Vec<MathType>, layer_count: Vec<usize>, var1: MathType,
             return (kek[0].parse().unwrap(), chars[0].parse().unwrap(), chars[1].parse().unwrap());
         }
 
-        fn index_from_string(s: &str) -> (usize, usize) {
-            let trim = s.replace("(", "").replace(")", "");
-            let kek = trim.split("^").last().unwrap().split("_").collect::<Vec<_>>();
-
-            return (kek[0].parse().unwrap(), kek[1].parse().unwrap());
+        fn neuron_index(layers: &[Vec<MathType>], parameter: &[MathType]) -> Option<(usize, usize)> {
+            layers.iter().enumerate().skip(1).find_map(|(layer_index, layer)| {
+                layer.iter().position(|x| x.parameter == parameter).map(|node_index| (layer_index, node_index))
+            })
         }
     }
 
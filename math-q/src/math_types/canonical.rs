@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+
+use super::{math_error::MathError, math_type::{self, MathType}, polynomial::{convert_fraction, reduce_fraction, Polynomial}, rational::Rational};
+
+//Atoms with integer exponents, sorted by atom and without zero exponents
+type Monomial = Vec<(MathType, i128)>;
+type Terms = BTreeMap<Monomial, Rational>;
+
+fn atom(tree: MathType, exponent: i128) -> Terms {
+    let mut terms = Terms::new();
+    terms.insert(vec![(tree, exponent)], Rational::ONE);
+    terms
+}
+
+fn add_term(terms: &mut Terms, monomial: Monomial, coefficient: Rational) -> Result<(), MathError> {
+    let sum = match terms.get(&monomial) {
+        Some(value) => value.checked_add(coefficient).ok_or(MathError::Overflow)?,
+        None => coefficient,
+    };
+
+    if sum.is_zero() {
+        terms.remove(&monomial);
+    } else {
+        terms.insert(monomial, sum);
+    }
+
+    Ok(())
+}
+
+fn multiply_monomials(left: &Monomial, right: &Monomial) -> Result<Monomial, MathError> {
+    let mut result = left.clone();
+
+    for (base, exponent) in right {
+        match result.binary_search_by(|x| x.0.cmp(base)) {
+            Ok(index) => {
+                result[index].1 = result[index].1.checked_add(*exponent).ok_or(MathError::Overflow)?;
+                if result[index].1 == 0 {
+                    result.remove(index);
+                }
+            },
+            Err(index) => result.insert(index, (base.clone(), *exponent)),
+        }
+    }
+
+    Ok(result)
+}
+
+fn multiply(left: &Terms, right: &Terms) -> Result<Terms, MathError> {
+    let mut result = Terms::new();
+
+    for (left_monomial, left_coefficient) in left {
+        for (right_monomial, right_coefficient) in right {
+            let coefficient = left_coefficient.checked_mul(*right_coefficient).ok_or(MathError::Overflow)?;
+            add_term(&mut result, multiply_monomials(left_monomial, right_monomial)?, coefficient)?;
+        }
+    }
+
+    Ok(result)
+}
+
+//Variables of the polynomial have to be sorted, so the monomials come out sorted by atom
+fn polynomial_terms(polynomial: &Polynomial) -> Terms {
+    let mut terms = Terms::new();
+
+    for (exponents, coefficient) in polynomial.terms() {
+        let monomial = polynomial.variables().iter().zip(exponents).filter(|(_, exponent)| **exponent > 0)
+            .map(|(base, exponent)| (base.clone(), *exponent as i128)).collect();
+
+        terms.insert(monomial, *coefficient);
+    }
+
+    terms
+}
+
+fn from_terms(terms: &Terms) -> MathType {
+    let mut summands = vec![];
+
+    for (monomial, coefficient) in terms {
+        let factors = monomial.iter().map(|(base, exponent)| {
+            if *exponent == 1 {
+                base.clone()
+            } else {
+                math_type::power(base.clone(), math_type::integer(*exponent))
+            }
+        }).collect::<Vec<MathType>>();
+
+        let rest = if factors.len() == 1 {
+            factors[0].clone()
+        } else {
+            math_type::product(factors)
+        };
+
+        summands.push(math_type::with_coefficient(*coefficient, rest));
+    }
+
+    let mut result = match summands.len() {
+        0 => math_type::natural_number(0),
+        1 => summands.remove(0),
+        _ => math_type::sum(summands),
+    };
+
+    result.sort();
+    result
+}
+
+impl MathType {
+    /*
+    Unique normal form for the rational fragment: numbers, variables, +, -, * and integer powers.
+    The expression is brought to p / q with gcd(p, q) = 1 and q normalized over a fixed order of the atoms,
+    so a.canonicalize() == b.canonicalize() exactly when a and b are equal as rational functions.
+    A monomial q is divided into the terms of p, any other q stays as a single factor q^-1.
+
+    Everything else (functions, exp, ln, powers with symbolic exponents) is canonicalized recursively and treated as an atom.
+    There equal normal forms still imply equality, but exp(a) exp(b) and exp(a + b) are not identified.
+
+    2 a + a b - a (1 + b) => a
+    x x^-1 => 1
+    (a + b)^2 => a^2 + 2 a b + b^2
+    (a + b)^2 / (a + b) => a + b
+    1 / (a + b) + 1 / (b + a) => 2 (a + b)^-1
+    1 / (a - b) + 1 / (a + b) => 2 a (a^2 - b^2)^-1
+     */
+    pub fn canonicalize(&self) -> MathType {
+        self.try_canonicalize().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_canonicalize(&self) -> Result<MathType, MathError> {
+        self.validate()?;
+
+        let mut variables = vec![];
+        let (numerator, denominator) = convert_fraction(&self.reduce_neutral(), &mut variables, &MathType::try_canonicalize)?;
+
+        //The sign and scale of the normalized denominator depend on the order of the atoms, which must not depend on the input
+        variables.sort();
+        let (numerator, denominator) = reduce_fraction(&numerator.with_variables(&variables), &denominator.with_variables(&variables))?;
+
+        let denominator = polynomial_terms(&denominator);
+        let inverse = if denominator.len() == 1 {
+            let (monomial, coefficient) = denominator.into_iter().next().unwrap();
+
+            let mut result = Terms::new();
+            result.insert(monomial.into_iter().map(|(x, e)| (x, -e)).collect(), coefficient.recip()?);
+            result
+        } else {
+            atom(from_terms(&denominator), -1)
+        };
+
+        Ok(from_terms(&multiply(&polynomial_terms(&numerator), &inverse)?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::math_types::math_type_name::MathTypeName;
+
+    use super::*;
+
+    fn canonical(input: &str) -> MathType {
+        MathType::parse(input).unwrap().canonicalize()
+    }
+
+    fn assert_same(a: &str, b: &str) {
+        let (left, right) = (canonical(a), canonical(b));
+        assert!(left == right, "{} => {} but {} => {}", a, left.get_string(), b, right.get_string());
+        assert_eq!(left.get_string(), right.get_string());
+    }
+
+    fn assert_different(a: &str, b: &str) {
+        assert!(canonical(a) != canonical(b), "{} and {} have the same normal form", a, b);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_same("2 + 3", "5");
+        assert_same("1 / 2 + 1 / 3", "rational(5, 6)");
+        assert_same("rational(-4, 2)", "-2");
+        assert_same("0 * x", "0");
+        assert!(canonical("rational(3, 4)").type_name == MathTypeName::Rational(Rational::new(3, 4)));
+        assert!(canonical("7").type_name == MathTypeName::NaturalNumber(7));
+    }
+
+    #[test]
+    fn sums_and_flipped_signs() {
+        assert_same("a + b", "b + a");
+        assert_same("2 * a + a * b - a * (1 + b)", "a");
+        assert_same("-(-(a))", "a");
+        assert_same("-(a - b)", "b - a");
+        assert_same("+()", "0");
+        assert_different("a - b", "b - a");
+    }
+
+    #[test]
+    fn products() {
+        assert_same("*(2, a)", "*(a, 2)");
+        assert_same("(a + b) * (a - b)", "a^2 - b^2");
+        assert_same("*()", "1");
+        assert_same("x * x^-1", "1");
+    }
+
+    #[test]
+    fn powers() {
+        assert_same("pow(x, 1)", "x");
+        assert_same("x^0", "1");
+        assert_same("(a + b)^2", "a^2 + 2 * a * b + b^2");
+        assert_same("x^(1 + 1)", "x * x");
+        assert_same("x^y * x^y", "(x^y)^2");
+        assert_different("x^y", "x^z");
+    }
+
+    #[test]
+    fn rational_functions() {
+        assert_same("(a + b)^2 / (a + b)", "a + b");
+        assert_same("1 / (a + b) + 1 / (b + a)", "2 / (a + b)");
+        assert_same("1 / (a - b) + 1 / (a + b)", "2 * a / (a^2 - b^2)");
+        assert_same("(x^2 - 1) / (x - 1)", "x + 1");
+        assert_same("1 / (b - a)", "-1 / (a - b)");
+        assert_same("(a + b) / (2 * a + 2 * b)", "rational(1, 2)");
+        assert_same("1 / a + 1 / b", "(a + b) / (a * b)");
+        assert_eq!(canonical("(a + b)^2 / (a + b)").get_string(), "+(a, b)");
+    }
+
+    #[test]
+    fn atoms_are_canonicalized_inside() {
+        assert_same("f(a + b, 2 * c)", "f(b + a, c * 2)");
+        assert_same("exp(x * (y + 1))", "exp(x + x * y)");
+        assert_same("ln((x^2 - 1) / (x + 1))", "ln(x - 1)");
+        assert_same("f(x) - f(x)", "0");
+        assert_different("exp(a) * exp(b)", "exp(a + b)");
+        assert_different("f(x, y)", "f(y, x)");
+    }
+
+    #[test]
+    fn undefined_is_an_atom() {
+        assert_same("Undefined + Undefined", "2 * Undefined");
+        assert!(canonical("Undefined").type_name == MathTypeName::Undefined);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(MathType::parse("1 / (a - a)").unwrap().try_canonicalize().err(), Some(MathError::DivisionByZero));
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use super::{eval::{EvalError, FunctionTable, NumericFunction}, math_type::MathType, math_type_name::MathTypeName};
+
+//Every instruction writes the register with its own index
+#[derive(Debug, Clone, Copy)]
+enum Instruction {
+    Constant(f64),
+    Variable(usize),
+    Add(usize, usize),
+    Mul(usize, usize),
+    Neg(usize),
+    Exp(usize),
+    Ln(usize),
+    Pow(usize, usize),
+    Powi(usize, i32),
+    //Parameters are arguments[start..start + count]
+    Call { function: usize, start: usize, count: usize },
+}
+
+pub struct CompiledExpression<'a> {
+    instructions: Vec<Instruction>,
+    variables: Vec<String>,
+    function_names: Vec<String>,
+    functions: Vec<&'a NumericFunction>,
+    //Implementation of the derivative f' for every function f, only needed by eval_gradient
+    derivatives: Vec<Option<&'a NumericFunction>>,
+    arguments: Vec<usize>,
+    registers: Vec<f64>,
+    adjoints: Vec<f64>,
+    call_buffer: Vec<f64>,
+}
+
+impl MathType {
+    //Variables are bound to slots in the order given, the inputs of eval have to follow it
+    pub fn compile<'a>(&self, variables: &[&str], functions: &'a FunctionTable) -> Result<CompiledExpression<'a>, EvalError> {
+        let mut compiled = CompiledExpression {
+            instructions: vec![],
+            variables: variables.iter().map(|x| x.to_string()).collect(),
+            function_names: vec![],
+            functions: vec![],
+            derivatives: vec![],
+            arguments: vec![],
+            registers: vec![],
+            adjoints: vec![],
+            call_buffer: vec![],
+        };
+
+        let mut function_names: Vec<String> = vec![];
+        compiled.lower(self, functions, &mut function_names)?;
+        compiled.function_names = function_names;
+
+        compiled.registers = vec![0.0; compiled.instructions.len()];
+        compiled.adjoints = vec![0.0; compiled.instructions.len()];
+
+        Ok(compiled)
+    }
+
+    //Value and partial derivatives of every variable in env, computed by one forward and one backward pass
+    pub fn eval_gradient(&self, env: &HashMap<String, f64>, functions: &FunctionTable) -> Result<(f64, HashMap<String, f64>), EvalError> {
+        let names = env.keys().map(|x| x.as_str()).collect::<Vec<&str>>();
+        let inputs = names.iter().map(|x| env[*x]).collect::<Vec<f64>>();
+
+        let mut compiled = self.compile(&names, functions)?;
+        let mut gradient = vec![0.0; names.len()];
+        let value = compiled.eval_gradient(&inputs, &mut gradient)?;
+
+        Ok((value, names.into_iter().map(|x| x.to_string()).zip(gradient).collect()))
+    }
+}
+
+impl<'a> CompiledExpression<'a> {
+    fn push(&mut self, instruction: Instruction) -> usize {
+        self.instructions.push(instruction);
+        self.instructions.len() - 1
+    }
+
+    //Returns the register holding the value of tree
+    fn lower(&mut self, tree: &MathType, functions: &'a FunctionTable, function_names: &mut Vec<String>) -> Result<usize, EvalError> {
+        match tree.type_name {
+            MathTypeName::Undefined => Err(EvalError::Undefined),
+            MathTypeName::NaturalNumber(value) => Ok(self.push(Instruction::Constant(value as f64))),
+            MathTypeName::Rational(value) => Ok(self.push(Instruction::Constant(value.to_f64()))),
+            MathTypeName::Function(ref name) => {
+                if tree.parameter.is_empty() {
+                    if let Some(slot) = self.variables.iter().position(|x| x == name) {
+                        return Ok(self.push(Instruction::Variable(slot)));
+                    }
+
+                    if !functions.contains_key(name) {
+                        return Err(EvalError::UnboundVariable(name.clone()));
+                    }
+                }
+
+                let function = match function_names.iter().position(|x| x == name) {
+                    Some(index) => index,
+                    None => {
+                        let implementation = functions.get(name).ok_or_else(|| EvalError::UnknownFunction(name.clone()))?;
+                        function_names.push(name.clone());
+                        self.functions.push(implementation.as_ref());
+                        self.derivatives.push(functions.get(&format!("{}'", name)).map(|x| x.as_ref()));
+                        self.functions.len() - 1
+                    },
+                };
+
+                let mut registers = vec![];
+                for para in &tree.parameter {
+                    registers.push(self.lower(para, functions, function_names)?);
+                }
+
+                let start = self.arguments.len();
+                self.arguments.extend(registers);
+
+                if self.call_buffer.len() < tree.parameter.len() {
+                    self.call_buffer.resize(tree.parameter.len(), 0.0);
+                }
+
+                Ok(self.push(Instruction::Call { function, start, count: tree.parameter.len() }))
+            },
+            MathTypeName::Sum | MathTypeName::Product => {
+                let neutral = if tree.type_name == MathTypeName::Sum { 0.0 } else { 1.0 };
+                if tree.parameter.is_empty() {
+                    return Ok(self.push(Instruction::Constant(neutral)));
+                }
+
+                let mut result = self.lower(&tree.parameter[0], functions, function_names)?;
+                for para in &tree.parameter[1..] {
+                    let next = self.lower(para, functions, function_names)?;
+
+                    result = if tree.type_name == MathTypeName::Sum {
+                        self.push(Instruction::Add(result, next))
+                    } else {
+                        self.push(Instruction::Mul(result, next))
+                    };
+                }
+
+                Ok(result)
+            },
+            MathTypeName::FlipSign => {
+                let inner = self.lower(&tree.parameter[0], functions, function_names)?;
+                Ok(self.push(Instruction::Neg(inner)))
+            },
+            MathTypeName::Exp => {
+                let inner = self.lower(&tree.parameter[0], functions, function_names)?;
+                Ok(self.push(Instruction::Exp(inner)))
+            },
+            MathTypeName::LogN => {
+                let inner = self.lower(&tree.parameter[0], functions, function_names)?;
+                Ok(self.push(Instruction::Ln(inner)))
+            },
+            MathTypeName::Power => {
+                let base = self.lower(&tree.parameter[0], functions, function_names)?;
+
+                //Integer exponents are the common case and powi is exact for negative bases
+                if let Some(exponent) = tree.parameter[1].constant_value() {
+                    if exponent.is_integer() {
+                        if let Ok(exponent) = i32::try_from(exponent.numerator()) {
+                            return Ok(self.push(Instruction::Powi(base, exponent)));
+                        }
+                    }
+                }
+
+                let exponent = self.lower(&tree.parameter[1], functions, function_names)?;
+                Ok(self.push(Instruction::Pow(base, exponent)))
+            },
+        }
+    }
+
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.len()
+    }
+
+    //inputs[slot] is the value of variables[slot]
+    pub fn eval(&mut self, inputs: &[f64]) -> f64 {
+        assert_eq!(inputs.len(), self.variables.len(), "Expected one input per variable");
+
+        for i in 0..self.instructions.len() {
+            let registers = &self.registers;
+
+            let value = match self.instructions[i] {
+                Instruction::Constant(value) => value,
+                Instruction::Variable(slot) => inputs[slot],
+                Instruction::Add(a, b) => registers[a] + registers[b],
+                Instruction::Mul(a, b) => registers[a] * registers[b],
+                Instruction::Neg(a) => -registers[a],
+                Instruction::Exp(a) => registers[a].exp(),
+                Instruction::Ln(a) => registers[a].ln(),
+                Instruction::Pow(a, b) => registers[a].powf(registers[b]),
+                Instruction::Powi(a, exponent) => registers[a].powi(exponent),
+                Instruction::Call { function, start, count } => {
+                    for j in 0..count {
+                        self.call_buffer[j] = registers[self.arguments[start + j]];
+                    }
+
+                    (self.functions[function])(&self.call_buffer[..count])
+                },
+            };
+
+            self.registers[i] = value;
+        }
+
+        *self.registers.last().unwrap()
+    }
+
+    //columns[slot][row] is the value of variables[slot] in the given row
+    pub fn eval_batch(&mut self, columns: &[&[f64]], output: &mut [f64]) {
+        assert_eq!(columns.len(), self.variables.len(), "Expected one column per variable");
+        assert!(columns.iter().all(|x| x.len() == output.len()), "Expected every column to have one entry per output row");
+
+        let mut inputs = vec![0.0; columns.len()];
+        for row in 0..output.len() {
+            for slot in 0..columns.len() {
+                inputs[slot] = columns[slot][row];
+            }
+
+            output[row] = self.eval(&inputs);
+        }
+    }
+
+    /*
+    Reverse mode: evaluates the tape, then propagates the adjoint of every register from the output back to the inputs.
+    gradient[slot] receives the partial derivative of variables[slot].
+    Like get_derivative, every parameter of f(a, b) is derived through the same f'.
+     */
+    pub fn eval_gradient(&mut self, inputs: &[f64], gradient: &mut [f64]) -> Result<f64, EvalError> {
+        assert_eq!(gradient.len(), self.variables.len(), "Expected one gradient entry per variable");
+
+        let value = self.eval(inputs);
+
+        gradient.fill(0.0);
+        self.adjoints.fill(0.0);
+        *self.adjoints.last_mut().unwrap() = 1.0;
+
+        for i in (0..self.instructions.len()).rev() {
+            let adjoint = self.adjoints[i];
+            if adjoint == 0.0 {
+                continue;
+            }
+
+            let registers = &self.registers;
+            let adjoints = &mut self.adjoints;
+
+            match self.instructions[i] {
+                Instruction::Constant(_) => {},
+                Instruction::Variable(slot) => gradient[slot] += adjoint,
+                Instruction::Add(a, b) => {
+                    adjoints[a] += adjoint;
+                    adjoints[b] += adjoint;
+                },
+                Instruction::Mul(a, b) => {
+                    adjoints[a] += adjoint * registers[b];
+                    adjoints[b] += adjoint * registers[a];
+                },
+                Instruction::Neg(a) => adjoints[a] -= adjoint,
+                Instruction::Exp(a) => adjoints[a] += adjoint * registers[i],
+                Instruction::Ln(a) => adjoints[a] += adjoint / registers[a],
+                Instruction::Pow(a, b) => {
+                    adjoints[a] += adjoint * registers[b] * registers[a].powf(registers[b] - 1.0);
+
+                    //ln of a non positive base is only needed if the power does not vanish
+                    if registers[i] != 0.0 {
+                        adjoints[b] += adjoint * registers[i] * registers[a].ln();
+                    }
+                },
+                Instruction::Powi(a, exponent) => {
+                    if exponent != 0 {
+                        adjoints[a] += adjoint * exponent as f64 * registers[a].powi(exponent - 1);
+                    }
+                },
+                Instruction::Call { function, start, count } => {
+                    for j in 0..count {
+                        self.call_buffer[j] = registers[self.arguments[start + j]];
+                    }
+
+                    let derivative = self.derivatives[function].ok_or_else(|| EvalError::UnknownFunction(format!("{}'", self.function_names[function])))?;
+                    let slope = adjoint * derivative(&self.call_buffer[..count]);
+
+                    for j in 0..count {
+                        adjoints[self.arguments[start + j]] += slope;
+                    }
+                },
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::math_types::math_type;
+
+    use super::*;
+
+    //Compares every partial derivative of eval_gradient with the symbolic derivative at env
+    fn assert_gradient_matches(input: &str, env: &[(&str, f64)], functions: &FunctionTable) {
+        let tree = MathType::parse(input).unwrap();
+        let env = env.iter().map(|(name, value)| (name.to_string(), *value)).collect::<HashMap<String, f64>>();
+
+        let (value, gradient) = tree.eval_gradient(&env, functions).unwrap();
+        assert!((value - tree.eval_with(&env, functions).unwrap()).abs() < 1e-9);
+
+        for (name, slope) in gradient {
+            let expected = tree.get_derivative(&math_type::variable(&name)).eval_with(&env, functions).unwrap();
+            assert!((slope - expected).abs() < 1e-9 * expected.abs().max(1.0), "{} wrt {}: {} != {}", input, name, slope, expected);
+        }
+    }
+
+    fn activations() -> FunctionTable {
+        let mut functions = FunctionTable::new();
+        functions.insert("f".to_string(), Box::new(|x: &[f64]| x.iter().sum::<f64>().tanh()));
+        functions.insert("f'".to_string(), Box::new(|x: &[f64]| 1.0 - x.iter().sum::<f64>().tanh().powi(2)));
+        functions
+    }
+
+    #[test]
+    fn gradient_of_sums_and_products() {
+        let env = [("x", 1.5), ("y", -0.5), ("z", 2.0)];
+        assert_gradient_matches("x + y - z", &env, &FunctionTable::new());
+        assert_gradient_matches("x * y * z + x * x", &env, &FunctionTable::new());
+        assert_gradient_matches("(x - y) * (y + 2 * z) * x", &env, &FunctionTable::new());
+    }
+
+    #[test]
+    fn gradient_of_powers() {
+        let env = [("x", 1.5), ("y", 0.75)];
+        assert_gradient_matches("x^3 + y^-2", &env, &FunctionTable::new());
+        assert_gradient_matches("x^y", &env, &FunctionTable::new());
+        assert_gradient_matches("2^(x * y) + x^(1/2)", &env, &FunctionTable::new());
+    }
+
+    #[test]
+    fn gradient_of_exp_and_ln() {
+        let env = [("x", 0.5), ("y", 2.5)];
+        assert_gradient_matches("exp(x * y) + ln(y)", &env, &FunctionTable::new());
+        assert_gradient_matches("ln(exp(x) + y^2) * x", &env, &FunctionTable::new());
+    }
+
+    #[test]
+    fn gradient_of_function_calls() {
+        let env = [("x", 0.3), ("y", -1.2), ("w", 0.8)];
+        assert_gradient_matches("f(x * w + y)", &env, &activations());
+        assert_gradient_matches("f(w * f(x * w) + y * x)", &env, &activations());
+        assert_gradient_matches("f(x, y * w) * x", &env, &activations());
+    }
+
+    #[test]
+    fn gradient_needs_the_derivative_of_calls() {
+        let mut functions = activations();
+        functions.remove("f'");
+
+        let env = HashMap::from([("x".to_string(), 1.0)]);
+        let result = MathType::parse("f(x)").unwrap().eval_gradient(&env, &functions);
+        assert!(result == Err(EvalError::UnknownFunction("f'".to_string())));
+    }
+
+    #[test]
+    fn batch_matches_eval() {
+        let tree = MathType::parse("x * y + exp(x) - y^2").unwrap();
+        let functions = FunctionTable::new();
+        let mut compiled = tree.compile(&["x", "y"], &functions).unwrap();
+
+        let xs = [0.0, 0.5, -1.5];
+        let ys = [1.0, 2.0, 3.0];
+        let mut output = [0.0; 3];
+        compiled.eval_batch(&[&xs, &ys], &mut output);
+
+        for row in 0..3 {
+            let env = HashMap::from([("x".to_string(), xs[row]), ("y".to_string(), ys[row])]);
+            assert_eq!(output[row], tree.eval(&env).unwrap());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "one entry per output row")]
+    fn batch_rejects_short_columns() {
+        let tree = MathType::parse("x + y").unwrap();
+        let functions = FunctionTable::new();
+        let mut compiled = tree.compile(&["x", "y"], &functions).unwrap();
+
+        let mut output = [0.0; 3];
+        compiled.eval_batch(&[&[1.0, 2.0, 3.0], &[1.0, 2.0]], &mut output);
+    }
+}
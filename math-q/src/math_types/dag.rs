@@ -0,0 +1,619 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use super::{math_error::MathError, math_type::MathType, math_type_name::MathTypeName, rational::Rational};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DagNode {
+    pub type_name: MathTypeName,
+    pub parameter: Vec<ExprId>,
+}
+
+/*
+Arena of interned nodes, identical subtrees are stored once and compare by id.
+Parameters of commutative nodes are kept sorted by id, so a + b and b + a share a node.
+ */
+pub struct ExprPool {
+    nodes: Vec<DagNode>,
+    lookup: HashMap<DagNode, ExprId>,
+}
+
+impl Default for ExprPool {
+    fn default() -> Self {
+        ExprPool::new()
+    }
+}
+
+impl ExprPool {
+    pub fn new() -> ExprPool {
+        ExprPool {
+            nodes: vec![],
+            lookup: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: ExprId) -> &DagNode {
+        &self.nodes[id.0]
+    }
+
+    pub fn try_intern(&mut self, type_name: MathTypeName, mut parameter: Vec<ExprId>) -> Result<ExprId, MathError> {
+        let (min, max) = type_name.parameter_range();
+        if parameter.len() < min || parameter.len() > max {
+            return Err(MathError::ArityMismatch {
+                type_name,
+                expected: (min, max),
+                found: parameter.len(),
+            });
+        }
+
+        if type_name.is_commutative() {
+            parameter.sort();
+        }
+
+        let node = DagNode { type_name, parameter };
+        if let Some(id) = self.lookup.get(&node) {
+            return Ok(*id);
+        }
+
+        let id = ExprId(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.lookup.insert(node, id);
+
+        Ok(id)
+    }
+
+    pub fn intern(&mut self, type_name: MathTypeName, parameter: Vec<ExprId>) -> ExprId {
+        self.try_intern(type_name, parameter).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn insert(&mut self, tree: &MathType) -> ExprId {
+        let parameter = tree.parameter.iter().map(|x| self.insert(x)).collect();
+        self.intern(tree.type_name.clone(), parameter)
+    }
+
+    //Like insert, also records the id of every node of tree by its address
+    pub(crate) fn insert_addresses(&mut self, tree: &MathType, ids: &mut HashMap<*const MathType, ExprId>) -> ExprId {
+        let parameter = tree.parameter.iter().map(|x| self.insert_addresses(x, ids)).collect();
+        let id = self.intern(tree.type_name.clone(), parameter);
+        ids.insert(tree, id);
+
+        id
+    }
+
+    pub fn to_math_type(&self, id: ExprId) -> MathType {
+        let node = self.node(id);
+        MathType::new(node.type_name.clone(), node.parameter.iter().map(|x| self.to_math_type(*x)).collect())
+    }
+
+    //Size of the expanded tree, saturates instead of overflowing on deep sharing
+    pub fn tree_size(&self, id: ExprId) -> u64 {
+        fn visit(pool: &ExprPool, id: ExprId, memo: &mut HashMap<ExprId, u64>) -> u64 {
+            if let Some(size) = memo.get(&id) {
+                return *size;
+            }
+
+            let mut size: u64 = 1;
+            for para in &pool.node(id).parameter {
+                size = size.saturating_add(visit(pool, *para, memo));
+            }
+
+            memo.insert(id, size);
+            size
+        }
+
+        visit(self, id, &mut HashMap::new())
+    }
+
+    //Number of unique nodes reachable from id
+    pub fn dag_size(&self, id: ExprId) -> usize {
+        let mut visited = HashSet::new();
+        let mut stack = vec![id];
+
+        while let Some(next) = stack.pop() {
+            if visited.insert(next) {
+                stack.extend(self.node(next).parameter.iter().copied());
+            }
+        }
+
+        visited.len()
+    }
+
+    pub fn constant_value(&self, id: ExprId) -> Option<Rational> {
+        match self.node(id).type_name {
+            MathTypeName::NaturalNumber(value) => Rational::try_from(value).ok(),
+            MathTypeName::Rational(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn number(&mut self, value: Rational) -> ExprId {
+        if value.is_integer() && !value.is_negative() {
+            return self.intern(MathTypeName::NaturalNumber(value.numerator() as u128), vec![]);
+        }
+
+        self.intern(MathTypeName::Rational(value), vec![])
+    }
+
+    /*
+    Smart constructors, they flatten, fold constants and drop neutral elements:
+    sum[a, sum[b, 2], 3] => sum[a, b, 5]
+    a + 2 a => 3 a
+    product[a, 0] => 0
+    product[a, a] => a^2
+    product[a] => a
+     */
+    pub fn sum(&mut self, parameter: Vec<ExprId>) -> ExprId {
+        let mut flat = vec![];
+        for para in parameter {
+            if self.node(para).type_name == MathTypeName::Sum {
+                flat.extend(self.node(para).parameter.iter().copied());
+            } else {
+                flat.push(para);
+            }
+        }
+
+        let mut constant = Rational::ZERO;
+        let mut terms: Vec<(Rational, ExprId)> = vec![];
+        let mut kept = vec![];
+
+        for para in flat {
+            if let Some(value) = self.constant_value(para) {
+                match constant.checked_add(value) {
+                    Some(next) => constant = next,
+                    None => kept.push(para),
+                }
+
+                continue;
+            }
+
+            let (coefficient, rest) = self.split_coefficient(para);
+            match terms.iter_mut().find(|(c, r)| *r == rest && c.checked_add(coefficient).is_some()) {
+                Some(term) => term.0 = term.0 + coefficient,
+                None => terms.push((coefficient, rest)),
+            }
+        }
+
+        let mut result = vec![];
+        if !constant.is_zero() {
+            result.push(self.number(constant));
+        }
+
+        for (coefficient, rest) in terms {
+            if coefficient.is_zero() {
+                continue;
+            }
+
+            let term = if coefficient.is_one() {
+                rest
+            } else if coefficient == -Rational::ONE {
+                self.flip_sign(rest)
+            } else {
+                let factor = self.number(coefficient);
+                self.product(vec![factor, rest])
+            };
+
+            result.push(term);
+        }
+
+        result.extend(kept);
+
+        match result.len() {
+            0 => self.number(Rational::ZERO),
+            1 => result[0],
+            _ => self.intern(MathTypeName::Sum, result),
+        }
+    }
+
+    pub fn product(&mut self, parameter: Vec<ExprId>) -> ExprId {
+        let mut flat = vec![];
+        for para in parameter {
+            if self.node(para).type_name == MathTypeName::Product {
+                flat.extend(self.node(para).parameter.iter().copied());
+            } else {
+                flat.push(para);
+            }
+        }
+
+        //a * a^2 => a^3, the first occurrence is kept when a base appears once
+        let mut factors: Vec<(ExprId, Vec<ExprId>, ExprId)> = vec![];
+        let mut combined = vec![];
+
+        for para in flat {
+            if self.constant_value(para).is_some() {
+                combined.push(para);
+                continue;
+            }
+
+            let (base, exponent) = self.split_exponent(para);
+            match factors.iter_mut().find(|x| x.0 == base) {
+                Some(factor) => factor.1.push(exponent),
+                None => factors.push((base, vec![exponent], para)),
+            }
+        }
+
+        for (base, exponents, first) in factors {
+            if exponents.len() == 1 {
+                combined.push(first);
+                continue;
+            }
+
+            let exponent = self.sum(exponents);
+            let factor = self.power(base, exponent);
+
+            if self.node(factor).type_name == MathTypeName::Product {
+                combined.extend(self.node(factor).parameter.iter().copied());
+            } else {
+                combined.push(factor);
+            }
+        }
+
+        let mut constant = Rational::ONE;
+        let mut result = vec![];
+
+        for para in combined {
+            if let Some(value) = self.constant_value(para) {
+                if let Some(next) = constant.checked_mul(value) {
+                    constant = next;
+                    continue;
+                }
+            }
+
+            result.push(para);
+        }
+
+        if constant.is_zero() {
+            return self.number(Rational::ZERO);
+        }
+
+        if !constant.is_one() {
+            let factor = self.number(constant);
+            result.insert(0, factor);
+        }
+
+        match result.len() {
+            0 => self.number(Rational::ONE),
+            1 => result[0],
+            _ => self.intern(MathTypeName::Product, result),
+        }
+    }
+
+    pub fn flip_sign(&mut self, id: ExprId) -> ExprId {
+        if let Some(value) = self.constant_value(id).and_then(|x| x.checked_neg()) {
+            return self.number(value);
+        }
+
+        if self.node(id).type_name == MathTypeName::FlipSign {
+            return self.node(id).parameter[0];
+        }
+
+        self.intern(MathTypeName::FlipSign, vec![id])
+    }
+
+    pub fn power(&mut self, base: ExprId, exponent: ExprId) -> ExprId {
+        if let Some(exponent_value) = self.constant_value(exponent) {
+            if exponent_value.is_zero() {
+                return self.number(Rational::ONE);
+            }
+
+            if exponent_value.is_one() {
+                return base;
+            }
+
+            if let (Some(base_value), true) = (self.constant_value(base), exponent_value.is_integer()) {
+                if let Ok(value) = i32::try_from(exponent_value.numerator()).map_err(|_| MathError::Overflow).and_then(|x| base_value.pow(x)) {
+                    return self.number(value);
+                }
+            }
+        }
+
+        self.intern(MathTypeName::Power, vec![base, exponent])
+    }
+
+    //2 * a * b => (2, a * b), -a => (-1, a)
+    fn split_coefficient(&mut self, id: ExprId) -> (Rational, ExprId) {
+        let node = self.node(id).clone();
+
+        match node.type_name {
+            MathTypeName::FlipSign => {
+                let (coefficient, rest) = self.split_coefficient(node.parameter[0]);
+                match coefficient.checked_neg() {
+                    Some(negated) => (negated, rest),
+                    None => (Rational::ONE, id),
+                }
+            },
+            MathTypeName::Product => match node.parameter.iter().position(|x| self.constant_value(*x).is_some()) {
+                Some(index) => {
+                    let coefficient = self.constant_value(node.parameter[index]).unwrap();
+                    let mut rest = node.parameter.clone();
+                    rest.remove(index);
+                    (coefficient, self.product(rest))
+                },
+                None => (Rational::ONE, id),
+            },
+            _ => (Rational::ONE, id),
+        }
+    }
+
+    //a^2 => (a, 2), a => (a, 1)
+    fn split_exponent(&mut self, id: ExprId) -> (ExprId, ExprId) {
+        let node = self.node(id);
+
+        if node.type_name == MathTypeName::Power {
+            return (node.parameter[0], node.parameter[1]);
+        }
+
+        (id, self.number(Rational::ONE))
+    }
+
+    //Rebuilds every unique node once through the smart constructors
+    pub fn simplify(&mut self, id: ExprId) -> ExprId {
+        fn visit(pool: &mut ExprPool, id: ExprId, memo: &mut HashMap<ExprId, ExprId>) -> ExprId {
+            if let Some(result) = memo.get(&id) {
+                return *result;
+            }
+
+            let node = pool.node(id).clone();
+            let parameter = node.parameter.iter().map(|x| visit(pool, *x, memo)).collect::<Vec<ExprId>>();
+
+            let result = match node.type_name {
+                MathTypeName::Sum => pool.sum(parameter),
+                MathTypeName::Product => pool.product(parameter),
+                MathTypeName::FlipSign => pool.flip_sign(parameter[0]),
+                MathTypeName::Power => pool.power(parameter[0], parameter[1]),
+                _ => pool.intern(node.type_name, parameter),
+            };
+
+            memo.insert(id, result);
+            result
+        }
+
+        visit(self, id, &mut HashMap::new())
+    }
+
+    pub fn depends_on(&self, id: ExprId, delta_var: ExprId) -> bool {
+        self.depends_on_memo(id, &self.node(delta_var).type_name, &mut HashMap::new())
+    }
+
+    //A function with the name of the variable counts as the variable, like in MathType::get_derivative
+    fn depends_on_memo(&self, id: ExprId, var_name: &MathTypeName, memo: &mut HashMap<ExprId, bool>) -> bool {
+        if let Some(result) = memo.get(&id) {
+            return *result;
+        }
+
+        let node = self.node(id);
+        let result = node.type_name == *var_name || node.parameter.iter().any(|x| self.depends_on_memo(*x, var_name, memo));
+
+        memo.insert(id, result);
+        result
+    }
+
+    //Same rules as MathType::get_derivative, every shared subterm is differentiated once
+    pub fn derivative(&mut self, id: ExprId, delta_var: ExprId) -> Result<ExprId, MathError> {
+        let var_node = self.node(delta_var).clone();
+        if !(matches!(var_node.type_name, MathTypeName::Function(_)) && var_node.parameter.is_empty()) {
+            return Err(MathError::NonVariableDerivative(self.to_math_type(delta_var).get_string()));
+        }
+
+        let mut dependent = HashMap::new();
+        let mut undefined = HashMap::new();
+        let mut memo = HashMap::new();
+
+        Ok(self.derivative_node(id, &var_node.type_name, &mut dependent, &mut undefined, &mut memo))
+    }
+
+    fn derivative_node(&mut self, id: ExprId, var_name: &MathTypeName, dependent: &mut HashMap<ExprId, bool>, undefined: &mut HashMap<ExprId, bool>,
+        memo: &mut HashMap<ExprId, ExprId>) -> ExprId {
+        if let Some(result) = memo.get(&id) {
+            return *result;
+        }
+
+        //Undefined stays Undefined like in MathType::get_derivative, so only subtrees without it are constant
+        if !self.depends_on_memo(id, var_name, dependent) && !self.depends_on_memo(id, &MathTypeName::Undefined, undefined) {
+            let zero = self.number(Rational::ZERO);
+            memo.insert(id, zero);
+            return zero;
+        }
+
+        let node = self.node(id).clone();
+
+        //The power rule depends on which side contains the variable
+        let (base_dependent, exponent_dependent) = if node.type_name == MathTypeName::Power {
+            (self.depends_on_memo(node.parameter[0], var_name, dependent), self.depends_on_memo(node.parameter[1], var_name, dependent))
+        } else {
+            (false, false)
+        };
+
+        let mut d = |pool: &mut ExprPool, x: ExprId| pool.derivative_node(x, var_name, dependent, undefined, memo);
+
+        let result = match node.type_name {
+            MathTypeName::Function(ref name) => {
+                if node.type_name == *var_name {
+                    self.number(Rational::ONE)
+                } else {
+                    let outer = self.intern(MathTypeName::Function(format!("{}'", name)), node.parameter.clone());
+                    let mut terms = vec![];
+                    for para in &node.parameter {
+                        let inner = d(self, *para);
+                        terms.push(self.product(vec![inner, outer]));
+                    }
+
+                    self.sum(terms)
+                }
+            },
+            MathTypeName::Sum => {
+                let terms = node.parameter.iter().map(|x| d(self, *x)).collect();
+                self.sum(terms)
+            },
+            MathTypeName::FlipSign => {
+                let inner = d(self, node.parameter[0]);
+                self.flip_sign(inner)
+            },
+            MathTypeName::Product => {
+                let mut terms = vec![];
+                for i in 0..node.parameter.len() {
+                    let factor = d(self, node.parameter[i]);
+                    if self.constant_value(factor).is_some_and(|x| x.is_zero()) {
+                        continue;
+                    }
+
+                    let mut factors = node.parameter.clone();
+                    factors[i] = factor;
+                    terms.push(self.product(factors));
+                }
+
+                self.sum(terms)
+            },
+            MathTypeName::Exp => {
+                let inner = d(self, node.parameter[0]);
+                self.product(vec![id, inner])
+            },
+            MathTypeName::LogN => {
+                let inner = d(self, node.parameter[0]);
+                let minus_one = self.number(-Rational::ONE);
+                let reciprocal = self.power(node.parameter[0], minus_one);
+                self.product(vec![inner, reciprocal])
+            },
+            MathTypeName::Power => {
+                let (base, exponent) = (node.parameter[0], node.parameter[1]);
+
+                if !exponent_dependent {
+                    let minus_one = self.number(-Rational::ONE);
+                    let lowered = self.sum(vec![exponent, minus_one]);
+                    let outer = self.power(base, lowered);
+                    let inner = d(self, base);
+                    self.product(vec![exponent, outer, inner])
+                } else if !base_dependent {
+                    let logarithm = self.intern(MathTypeName::LogN, vec![base]);
+                    let inner = d(self, exponent);
+                    self.product(vec![id, logarithm, inner])
+                } else {
+                    let logarithm = self.intern(MathTypeName::LogN, vec![base]);
+                    let exponent_derivative = d(self, exponent);
+                    let base_derivative = d(self, base);
+                    let minus_one = self.number(-Rational::ONE);
+                    let reciprocal = self.power(base, minus_one);
+
+                    let left = self.product(vec![exponent_derivative, logarithm]);
+                    let right = self.product(vec![exponent, base_derivative, reciprocal]);
+                    let inner = self.sum(vec![left, right]);
+                    self.product(vec![id, inner])
+                }
+            },
+            MathTypeName::Undefined => id,
+            _ => self.number(Rational::ZERO),
+        };
+
+        memo.insert(id, result);
+        result
+    }
+}
+
+//Size of a MathType as a tree and with identical subtrees stored once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeCount {
+    pub tree: u64,
+    pub unique: usize,
+}
+
+impl fmt::Display for NodeCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nodes, {} unique", self.tree, self.unique)
+    }
+}
+
+impl MathType {
+    pub fn count_nodes(&self) -> NodeCount {
+        NodeCount {
+            tree: self.tree_size(),
+            unique: self.count_unique_nodes(),
+        }
+    }
+
+    //Number of distinct subtrees, tree_size counts the expanded tree
+    pub fn count_unique_nodes(&self) -> usize {
+        let mut pool = ExprPool::new();
+        let id = pool.insert(self);
+        pool.dag_size(id)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_types::math_type;
+
+    fn pool_derivative(tree: &MathType, var: &str) -> MathType {
+        let mut pool = ExprPool::new();
+        let id = pool.insert(tree);
+        let var = pool.insert(&math_type::variable(var));
+        let derivative = pool.derivative(id, var).unwrap();
+
+        pool.to_math_type(derivative)
+    }
+
+    #[test]
+    fn derivative_matches_the_tree() {
+        let x = math_type::variable("x");
+        for input in [
+            "x^3 + 2 * x - 7",
+            "(x + 1) * (x - 1) * x",
+            "x^y + 2^x + x^x",
+            "exp(x * y) + ln(x^2 + 1)",
+            "f(x^2, y) * g(x)",
+            "(x + y)^2 * (x + y)^2 + ln(x + y)",
+            "y * z",
+        ] {
+            let tree = MathType::parse(input).unwrap();
+            assert!(pool_derivative(&tree, "x").simplify() == tree.get_derivative(&x).simplify(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn undefined_stays_undefined() {
+        let undefined = MathType::new(MathTypeName::Undefined, vec![]);
+        let x = math_type::variable("x");
+
+        assert!(pool_derivative(&undefined, "x") == undefined);
+        assert!(undefined.get_derivative(&x) == undefined);
+
+        let tree = math_type::sum(vec![undefined.clone(), math_type::variable("y")]);
+        assert!(pool_derivative(&tree, "x").simplify() == tree.get_derivative(&x).simplify());
+    }
+
+    #[test]
+    fn simplify_matches_the_tree() {
+        for input in ["x + 0 + 2 * 3", "1 * (x * y) * x^1", "-(-x) + x^0", "(x + 1) * y + 0 * z"] {
+            let tree = MathType::parse(input).unwrap();
+
+            let mut pool = ExprPool::new();
+            let id = pool.insert(&tree);
+            let simplified = pool.simplify(id);
+
+            assert!(pool.to_math_type(simplified).simplify() == tree.simplify(), "{}", input);
+            assert!(pool.tree_size(simplified) <= tree.tree_size(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn identical_subtrees_are_stored_once() {
+        let mut pool = ExprPool::new();
+        let a = pool.insert(&MathType::parse("x * y + 1").unwrap());
+        let b = pool.insert(&MathType::parse("1 + y * x").unwrap());
+        assert!(a == b);
+
+        let tree = MathType::parse("(x + 1) * (x + 1)").unwrap();
+        assert!(tree.count_nodes() == NodeCount { tree: 7, unique: 4 });
+        assert_eq!(tree.count_nodes().to_string(), "7 nodes, 4 unique");
+    }
+}
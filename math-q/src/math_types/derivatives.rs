@@ -0,0 +1,113 @@
+use super::{math_error::MathError, math_type::MathType};
+
+pub fn gradient(expr: &MathType, vars: &[MathType]) -> Vec<MathType> {
+    try_gradient(expr, vars).unwrap_or_else(|error| panic!("{}", error))
+}
+
+//Simplified first derivatives in the order of vars
+pub fn try_gradient(expr: &MathType, vars: &[MathType]) -> Result<Vec<MathType>, MathError> {
+    let mut result = vec![];
+    for var in vars {
+        result.push(expr.try_get_derivative(var)?.try_simplify()?);
+    }
+
+    Ok(result)
+}
+
+pub fn jacobian(exprs: &[MathType], vars: &[MathType]) -> Vec<Vec<MathType>> {
+    try_jacobian(exprs, vars).unwrap_or_else(|error| panic!("{}", error))
+}
+
+//result[i][j] is the derivative of exprs[i] with respect to vars[j]
+pub fn try_jacobian(exprs: &[MathType], vars: &[MathType]) -> Result<Vec<Vec<MathType>>, MathError> {
+    exprs.iter().map(|expr| try_gradient(expr, vars)).collect()
+}
+
+pub fn hessian(expr: &MathType, vars: &[MathType]) -> Vec<Vec<MathType>> {
+    try_hessian(expr, vars).unwrap_or_else(|error| panic!("{}", error))
+}
+
+/*
+result[i][j] is the derivative of expr with respect to vars[i] and vars[j].
+The simplified gradient is computed once and every second derivative starts from it,
+only the upper triangle is derived and mirrored.
+ */
+pub fn try_hessian(expr: &MathType, vars: &[MathType]) -> Result<Vec<Vec<MathType>>, MathError> {
+    let first = try_gradient(expr, vars)?;
+
+    let mut result: Vec<Vec<MathType>> = vec![];
+    for i in 0..vars.len() {
+        let mut row = vec![];
+        for j in 0..vars.len() {
+            if j < i {
+                row.push(result[j][i].clone());
+            } else {
+                row.push(first[i].try_get_derivative(&vars[j])?.try_simplify()?);
+            }
+        }
+
+        result.push(row);
+    }
+
+    Ok(result)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::math_types::math_type;
+
+    fn vars(names: &[&str]) -> Vec<MathType> {
+        names.iter().map(|x| math_type::variable(x)).collect()
+    }
+
+    fn eval(tree: &MathType) -> f64 {
+        let env = HashMap::from([("x".to_string(), 0.4), ("y".to_string(), -1.3), ("z".to_string(), 2.1)]);
+        tree.eval(&env).unwrap()
+    }
+
+    #[test]
+    fn jacobian_of_a_vector_function() {
+        let vars = vars(&["x", "y"]);
+        let exprs = ["x * y", "x + y^2", "exp(x) * y", "ln(x^2 + y^2)"].map(|x| MathType::parse(x).unwrap());
+
+        let result = jacobian(&exprs, &vars);
+        assert_eq!(result.len(), exprs.len());
+
+        for (row, expr) in result.iter().zip(&exprs) {
+            assert_eq!(row.len(), vars.len());
+
+            for (entry, var) in row.iter().zip(&vars) {
+                assert!(*entry == expr.get_derivative(var).simplify());
+            }
+        }
+
+        assert!(result[0][0] == math_type::variable("y") && result[1][1] == MathType::parse("2 * y").unwrap().simplify());
+    }
+
+    #[test]
+    fn hessian_is_symmetric() {
+        let vars = vars(&["x", "y", "z"]);
+        let expr = MathType::parse("x^2 * y * z + exp(x * y) + ln(y^2 + 1) * z^3").unwrap();
+
+        let result = hessian(&expr, &vars);
+        for i in 0..vars.len() {
+            for j in 0..vars.len() {
+                assert!(result[i][j] == result[j][i]);
+
+                //Derived in the other order, which hessian never computes
+                let expected = expr.get_derivative(&vars[j]).get_derivative(&vars[i]);
+                assert!((eval(&result[i][j]) - eval(&expected)).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn gradient_rejects_non_variables() {
+        let result = try_gradient(&MathType::parse("x * y").unwrap(), &[MathType::parse("x * y").unwrap()]);
+        assert!(matches!(result, Err(MathError::NonVariableDerivative(_))));
+    }
+}
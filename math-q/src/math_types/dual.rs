@@ -0,0 +1,356 @@
+use std::{collections::HashMap, ops::{Add, Mul, Neg}};
+
+use super::{eval::{EvalError, FunctionTable}, math_type::MathType, math_type_name::MathTypeName};
+
+//real + e1 ε with ε² = 0, e1 is the derivative in the seeded direction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual {
+    pub real: f64,
+    pub e1: f64,
+}
+
+//real + e1 ε1 + e2 ε2 + e12 ε1 ε2 with ε1² = ε2² = 0, e12 is the mixed second derivative
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HyperDual {
+    pub real: f64,
+    pub e1: f64,
+    pub e2: f64,
+    pub e12: f64,
+}
+
+pub trait DualNumber: Copy + Add<Output = Self> + Mul<Output = Self> + Neg<Output = Self> {
+    //Highest derivative carried, a function f needs implementations of f', f'' up to this order
+    const ORDER: usize;
+
+    fn constant(value: f64) -> Self;
+    fn real(&self) -> f64;
+
+    //g(self) given g, g' and g'' at the real part, unused orders are ignored
+    fn chain(self, derivatives: [f64; 3]) -> Self;
+}
+
+impl Dual {
+    pub fn variable(value: f64) -> Dual {
+        Dual { real: value, e1: 1.0 }
+    }
+}
+
+impl HyperDual {
+    //Seeds the direction of e1, e2 or both
+    pub fn variable(value: f64, first: bool, second: bool) -> HyperDual {
+        HyperDual {
+            real: value,
+            e1: if first { 1.0 } else { 0.0 },
+            e2: if second { 1.0 } else { 0.0 },
+            e12: 0.0,
+        }
+    }
+}
+
+impl DualNumber for Dual {
+    const ORDER: usize = 1;
+
+    fn constant(value: f64) -> Dual {
+        Dual { real: value, e1: 0.0 }
+    }
+
+    fn real(&self) -> f64 {
+        self.real
+    }
+
+    fn chain(self, derivatives: [f64; 3]) -> Dual {
+        Dual {
+            real: derivatives[0],
+            e1: derivatives[1] * self.e1,
+        }
+    }
+}
+
+impl DualNumber for HyperDual {
+    const ORDER: usize = 2;
+
+    fn constant(value: f64) -> HyperDual {
+        HyperDual { real: value, e1: 0.0, e2: 0.0, e12: 0.0 }
+    }
+
+    fn real(&self) -> f64 {
+        self.real
+    }
+
+    fn chain(self, derivatives: [f64; 3]) -> HyperDual {
+        HyperDual {
+            real: derivatives[0],
+            e1: derivatives[1] * self.e1,
+            e2: derivatives[1] * self.e2,
+            e12: derivatives[1] * self.e12 + derivatives[2] * self.e1 * self.e2,
+        }
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+
+    fn add(self, other: Dual) -> Dual {
+        Dual { real: self.real + other.real, e1: self.e1 + other.e1 }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+
+    fn mul(self, other: Dual) -> Dual {
+        Dual {
+            real: self.real * other.real,
+            e1: self.e1 * other.real + self.real * other.e1,
+        }
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+
+    fn neg(self) -> Dual {
+        Dual { real: -self.real, e1: -self.e1 }
+    }
+}
+
+impl Add for HyperDual {
+    type Output = HyperDual;
+
+    fn add(self, other: HyperDual) -> HyperDual {
+        HyperDual {
+            real: self.real + other.real,
+            e1: self.e1 + other.e1,
+            e2: self.e2 + other.e2,
+            e12: self.e12 + other.e12,
+        }
+    }
+}
+
+impl Mul for HyperDual {
+    type Output = HyperDual;
+
+    fn mul(self, other: HyperDual) -> HyperDual {
+        HyperDual {
+            real: self.real * other.real,
+            e1: self.e1 * other.real + self.real * other.e1,
+            e2: self.e2 * other.real + self.real * other.e2,
+            e12: self.e12 * other.real + self.e1 * other.e2 + self.e2 * other.e1 + self.real * other.e12,
+        }
+    }
+}
+
+impl Neg for HyperDual {
+    type Output = HyperDual;
+
+    fn neg(self) -> HyperDual {
+        HyperDual { real: -self.real, e1: -self.e1, e2: -self.e2, e12: -self.e12 }
+    }
+}
+
+//x^n, n x^(n - 1), n (n - 1) x^(n - 2), powi keeps negative bases with integer exponents exact
+fn power_derivatives(base: f64, exponent: f64) -> [f64; 3] {
+    let power = |n: f64| if exponent.fract() == 0.0 && exponent.abs() < i32::MAX as f64 {
+        base.powi(n as i32)
+    } else {
+        base.powf(n)
+    };
+
+    [
+        power(exponent),
+        exponent * power(exponent - 1.0),
+        exponent * (exponent - 1.0) * power(exponent - 2.0),
+    ]
+}
+
+impl MathType {
+    //Value and the derivative with respect to var
+    pub fn eval_dual(&self, env: &HashMap<String, f64>, functions: &FunctionTable, var: &str) -> Result<Dual, EvalError> {
+        let env = env.iter().map(|(name, value)| {
+            let number = if name == var { Dual::variable(*value) } else { Dual::constant(*value) };
+            (name.clone(), number)
+        }).collect();
+
+        self.eval_numbers(&env, functions)
+    }
+
+    /*
+    Value, the derivatives with respect to first and second and the mixed derivative d²/d first d second in one pass.
+    first == second gives the second derivative with respect to that variable.
+     */
+    pub fn eval_hyper_dual(&self, env: &HashMap<String, f64>, functions: &FunctionTable, first: &str, second: &str) -> Result<HyperDual, EvalError> {
+        let env = env.iter().map(|(name, value)| {
+            (name.clone(), HyperDual::variable(*value, name == first, name == second))
+        }).collect();
+
+        self.eval_numbers(&env, functions)
+    }
+
+    /*
+    Evaluates with any dual number type, the seeds of the variables choose the directions.
+    Like get_derivative, every parameter of f(a, b) is derived through the same f' and f''.
+     */
+    pub fn eval_numbers<T: DualNumber>(&self, env: &HashMap<String, T>, functions: &FunctionTable) -> Result<T, EvalError> {
+        match self.type_name {
+            MathTypeName::Undefined => Err(EvalError::Undefined),
+            MathTypeName::NaturalNumber(value) => Ok(T::constant(value as f64)),
+            MathTypeName::Rational(value) => Ok(T::constant(value.to_f64())),
+            MathTypeName::Function(ref name) => {
+                if self.parameter.is_empty() {
+                    if let Some(value) = env.get(name) {
+                        return Ok(*value);
+                    }
+
+                    let function = functions.get(name).ok_or_else(|| EvalError::UnboundVariable(name.clone()))?;
+                    return Ok(T::constant(function(&[])));
+                }
+
+                let mut values = vec![];
+                let mut inner = T::constant(0.0);
+                for para in &self.parameter {
+                    let value = para.eval_numbers(env, functions)?;
+                    values.push(value.real());
+                    inner = inner + value;
+                }
+
+                let mut derivatives = [0.0; 3];
+                let mut derivative_name = name.clone();
+                for derivative in derivatives.iter_mut().take(T::ORDER + 1) {
+                    let function = functions.get(&derivative_name).ok_or_else(|| EvalError::UnknownFunction(derivative_name.clone()))?;
+                    *derivative = function(&values);
+
+                    derivative_name.push('\'');
+                }
+
+                Ok(inner.chain(derivatives))
+            },
+            MathTypeName::Sum => {
+                let mut sum = T::constant(0.0);
+                for para in &self.parameter {
+                    sum = sum + para.eval_numbers(env, functions)?;
+                }
+
+                Ok(sum)
+            },
+            MathTypeName::Product => {
+                let mut product = T::constant(1.0);
+                for para in &self.parameter {
+                    product = product * para.eval_numbers(env, functions)?;
+                }
+
+                Ok(product)
+            },
+            MathTypeName::FlipSign => Ok(-self.parameter[0].eval_numbers(env, functions)?),
+            MathTypeName::Exp => {
+                let inner = self.parameter[0].eval_numbers(env, functions)?;
+                let value = inner.real().exp();
+
+                Ok(inner.chain([value, value, value]))
+            },
+            MathTypeName::LogN => {
+                let inner = self.parameter[0].eval_numbers(env, functions)?;
+                let value = inner.real();
+
+                Ok(inner.chain([value.ln(), 1.0 / value, -1.0 / (value * value)]))
+            },
+            MathTypeName::Power => {
+                let base = self.parameter[0].eval_numbers(env, functions)?;
+
+                //Also catches -(1) from fraction, which would otherwise take the ln of a negative base
+                if let Some(exponent) = self.parameter[1].reduce_neutral().constant_value() {
+                    return Ok(base.chain(power_derivatives(base.real(), exponent.to_f64())));
+                }
+
+                //a^b = exp(b ln(a))
+                let exponent = self.parameter[1].eval_numbers(env, functions)?;
+                let value = base.real();
+                let product = exponent * base.chain([value.ln(), 1.0 / value, -1.0 / (value * value)]);
+                let result = product.real().exp();
+
+                Ok(product.chain([result, result, result]))
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_types::math_type;
+
+    fn env() -> HashMap<String, f64> {
+        HashMap::from([("x".to_string(), 0.7), ("y".to_string(), 1.6)])
+    }
+
+    fn activations() -> FunctionTable {
+        let mut functions = FunctionTable::new();
+        functions.insert("f".to_string(), Box::new(|x: &[f64]| x.iter().sum::<f64>().tanh()));
+        functions.insert("f'".to_string(), Box::new(|x: &[f64]| 1.0 - x.iter().sum::<f64>().tanh().powi(2)));
+        functions.insert("f''".to_string(), Box::new(|x: &[f64]| {
+            let t = x.iter().sum::<f64>().tanh();
+            -2.0 * t * (1.0 - t * t)
+        }));
+        functions
+    }
+
+    fn symbolic(tree: &MathType, vars: &[&str]) -> f64 {
+        let mut derivative = tree.clone();
+        for var in vars {
+            derivative = derivative.get_derivative(&math_type::variable(var));
+        }
+
+        derivative.eval_with(&env(), &activations()).unwrap()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1.0)
+    }
+
+    const INPUTS: [&str; 7] = [
+        "x^3 * y + 2 * x - y",
+        "(x + y) * (x - 2 * y) * x",
+        "x^y + 2^x + y^(1/2)",
+        "exp(x * y) - ln(x^2 + y)",
+        "f(x * y + 1) * x",
+        "f(f(x) * y)",
+        "x / (1 + y^2)",
+    ];
+
+    #[test]
+    fn dual_matches_the_first_derivative() {
+        for input in INPUTS {
+            let tree = MathType::parse(input).unwrap();
+
+            for var in ["x", "y"] {
+                let dual = tree.eval_dual(&env(), &activations(), var).unwrap();
+                assert!(close(dual.real, tree.eval_with(&env(), &activations()).unwrap()), "{}", input);
+                assert!(close(dual.e1, symbolic(&tree, &[var])), "{} wrt {}", input, var);
+            }
+        }
+    }
+
+    #[test]
+    fn hyper_dual_matches_the_second_derivatives() {
+        for input in INPUTS {
+            let tree = MathType::parse(input).unwrap();
+
+            for (first, second) in [("x", "y"), ("x", "x"), ("y", "y")] {
+                let hyper = tree.eval_hyper_dual(&env(), &activations(), first, second).unwrap();
+                assert!(close(hyper.e1, symbolic(&tree, &[first])), "{} wrt {}", input, first);
+                assert!(close(hyper.e2, symbolic(&tree, &[second])), "{} wrt {}", input, second);
+                assert!(close(hyper.e12, symbolic(&tree, &[first, second])), "{} wrt {} {}", input, first, second);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_derivative_is_an_error() {
+        let mut functions = activations();
+        functions.remove("f''");
+
+        let tree = MathType::parse("f(x)").unwrap();
+        assert!(tree.eval_dual(&env(), &functions, "x").is_ok());
+        assert!(tree.eval_hyper_dual(&env(), &functions, "x", "x").is_err());
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+
+use super::{math_error::MathError, math_type::{self, MathType}, math_type_name::MathTypeName};
+
+//Cost of a whole tree, extraction picks the cheapest tree of every class
+pub type CostFunction = dyn Fn(&MathType) -> u64;
+
+pub fn node_count(tree: &MathType) -> u64 {
+    tree.tree_size()
+}
+
+pub fn typst_length(tree: &MathType) -> u64 {
+    tree.get_typst_string().chars().count() as u64
+}
+
+//The existing transformations, each one is a rewrite from a term to an equal term
+const TRANSFORMATIONS: [fn(&MathType) -> MathType; 5] = [
+    MathType::expand,
+    MathType::flatten,
+    MathType::reduce_neutral,
+    MathType::combine,
+    MathType::factor_out,
+];
+
+//Matching builds at most this many terms per node and iteration
+const INSTANCE_LIMIT: usize = 64;
+
+//Variables with this prefix stand for whole classes while matching, the parsers do not accept # in names
+const PLACEHOLDER_PREFIX: &str = "#class";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EClassId(usize);
+
+//Parameters of commutative nodes are sorted by class id
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ENode {
+    pub type_name: MathTypeName,
+    pub parameter: Vec<EClassId>,
+}
+
+pub struct EGraph {
+    parents: Vec<usize>,
+    memo: HashMap<ENode, EClassId>,
+}
+
+impl Default for EGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EGraph {
+    pub fn new() -> EGraph {
+        EGraph {
+            parents: vec![],
+            memo: HashMap::new(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.memo.len()
+    }
+
+    pub fn find(&self, id: EClassId) -> EClassId {
+        let mut current = id.0;
+        while self.parents[current] != current {
+            current = self.parents[current];
+        }
+
+        EClassId(current)
+    }
+
+    fn canonicalize(&self, node: &ENode) -> ENode {
+        let mut parameter = node.parameter.iter().map(|x| self.find(*x)).collect::<Vec<EClassId>>();
+        if node.type_name.is_commutative() {
+            parameter.sort();
+        }
+
+        ENode { type_name: node.type_name.clone(), parameter }
+    }
+
+    pub fn add(&mut self, node: ENode) -> EClassId {
+        let node = self.canonicalize(&node);
+        if let Some(id) = self.memo.get(&node) {
+            return self.find(*id);
+        }
+
+        let id = EClassId(self.parents.len());
+        self.parents.push(id.0);
+        self.memo.insert(node, id);
+
+        id
+    }
+
+    pub fn add_tree(&mut self, tree: &MathType) -> EClassId {
+        let parameter = tree.parameter.iter().map(|x| self.add_tree(x)).collect();
+        self.add(ENode { type_name: tree.type_name.clone(), parameter })
+    }
+
+    //Returns false if both were already in the same class
+    pub fn union(&mut self, a: EClassId, b: EClassId) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+
+        //The older class stays the root
+        let (root, child) = if a < b { (a, b) } else { (b, a) };
+        self.parents[child.0] = root.0;
+
+        true
+    }
+
+    //Restores congruence: nodes with equal parameters after a union belong to one class
+    pub fn rebuild(&mut self) {
+        loop {
+            let mut memo: HashMap<ENode, EClassId> = HashMap::new();
+            let mut unions = vec![];
+
+            for (node, id) in self.memo.drain().collect::<Vec<(ENode, EClassId)>>() {
+                let node = self.canonicalize(&node);
+                let id = self.find(id);
+
+                match memo.get(&node) {
+                    Some(other) => unions.push((*other, id)),
+                    None => {
+                        memo.insert(node, id);
+                    },
+                }
+            }
+
+            self.memo = memo;
+
+            let mut changed = false;
+            for (a, b) in unions {
+                changed |= self.union(a, b);
+            }
+
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    //Cheapest tree of every class, built bottom up until no class improves
+    pub fn extract_all(&self, cost: &CostFunction) -> HashMap<EClassId, (u64, MathType)> {
+        let mut best: HashMap<EClassId, (u64, MathType)> = HashMap::new();
+
+        loop {
+            let mut changed = false;
+
+            for (node, id) in &self.memo {
+                let Some(tree) = self.build(node, &best) else {
+                    continue;
+                };
+
+                let id = self.find(*id);
+                let tree_cost = cost(&tree);
+
+                //Ties go to fewer nodes and then to the smaller tree, so the result does not depend on the hash map order.
+                //Preferring fewer nodes also stops cycles like a = sum[a] from growing the tree when the cost stays the same.
+                let better = |(current, current_tree): &(u64, MathType)| {
+                    (tree_cost, tree.tree_size(), &tree) < (*current, current_tree.tree_size(), current_tree)
+                };
+
+                if best.get(&id).is_none_or(better) {
+                    best.insert(id, (tree_cost, tree));
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                return best;
+            }
+        }
+    }
+
+    pub fn extract(&self, id: EClassId, cost: &CostFunction) -> Option<MathType> {
+        self.extract_all(cost).remove(&self.find(id)).map(|(_, tree)| tree)
+    }
+
+    fn build(&self, node: &ENode, best: &HashMap<EClassId, (u64, MathType)>) -> Option<MathType> {
+        let mut parameter = vec![];
+        for para in &node.parameter {
+            parameter.push(best.get(&self.find(*para))?.1.clone());
+        }
+
+        Some(MathType::new(node.type_name.clone(), parameter))
+    }
+
+    /*
+    Nodes of every class that are worth matching, ordered so saturation does not depend on the hash map order.
+    A class containing a number is only that number, and nodes with their own class as parameter are skipped.
+    Both would only grow the graph, like 1 = 1 * 1 = 1 * 1 * 1 or a = a * 1.
+     */
+    fn classes(&self) -> Vec<(EClassId, Vec<ENode>)> {
+        let mut classes: HashMap<EClassId, Vec<ENode>> = HashMap::new();
+        for (node, id) in &self.memo {
+            let id = self.find(*id);
+            let node = self.canonicalize(node);
+
+            if !node.parameter.contains(&id) {
+                classes.entry(id).or_default().push(node);
+            }
+        }
+
+        let mut classes = classes.into_iter().collect::<Vec<(EClassId, Vec<ENode>)>>();
+        classes.sort_by_key(|x| x.0);
+        for (_, nodes) in classes.iter_mut() {
+            if let Some(number) = nodes.iter().find(|x| x.type_name.is_number()) {
+                *nodes = vec![number.clone()];
+            }
+
+            nodes.sort_by_cached_key(|x| format!("{:?}", x));
+        }
+
+        classes
+    }
+
+    /*
+    Terms of depth two matching node: every parameter is either its class as a placeholder variable,
+    or one node of that class with placeholders as parameters.
+    At most INSTANCE_LIMIT terms are built per node.
+     */
+    fn instances(&self, node: &ENode, nodes: &HashMap<EClassId, &Vec<ENode>>, placeholders: &mut HashMap<String, EClassId>) -> Vec<MathType> {
+        let mut placeholder = |id: EClassId| {
+            let name = format!("{}{}", PLACEHOLDER_PREFIX, id.0);
+            placeholders.insert(name.clone(), id);
+            math_type::variable(&name)
+        };
+
+        let mut instances: Vec<Vec<MathType>> = vec![vec![]];
+        for para in &node.parameter {
+            let id = self.find(*para);
+
+            let mut options = vec![placeholder(id)];
+            for child in nodes.get(&id).into_iter().flat_map(|x| x.iter()) {
+                options.push(MathType::new(child.type_name.clone(), child.parameter.iter().map(|x| placeholder(self.find(*x))).collect()));
+            }
+
+            let mut next = vec![];
+            'outer: for instance in &instances {
+                for option in &options {
+                    if next.len() >= INSTANCE_LIMIT {
+                        break 'outer;
+                    }
+
+                    let mut instance = instance.clone();
+                    instance.push(option.clone());
+                    next.push(instance);
+                }
+            }
+
+            instances = next;
+        }
+
+        instances.into_iter().map(|x| MathType::new(node.type_name.clone(), x)).collect()
+    }
+
+    //Like add_tree, placeholder variables are replaced by their class
+    fn add_instance(&mut self, tree: &MathType, placeholders: &HashMap<String, EClassId>) -> EClassId {
+        if let MathTypeName::Function(name) = &tree.type_name {
+            if let Some(id) = placeholders.get(name).filter(|_| tree.parameter.is_empty()) {
+                return self.find(*id);
+            }
+        }
+
+        let parameter = tree.parameter.iter().map(|x| self.add_instance(x, placeholders)).collect();
+        self.add(ENode { type_name: tree.type_name.clone(), parameter })
+    }
+
+    /*
+    Equality saturation: every transformation is matched against the nodes of every class,
+    looking one level into the parameter classes, and the result joins the class of the matched node.
+    All matches of an iteration are collected before the graph changes.
+    Stops when nothing changes, after the given number of iterations or once the graph holds node_limit nodes.
+    Returns true if it stopped because nothing changed.
+     */
+    pub fn saturate(&mut self, iterations: usize, node_limit: usize) -> bool {
+        for _ in 0..iterations {
+            let classes = self.classes();
+            let nodes = classes.iter().map(|(id, nodes)| (*id, nodes)).collect::<HashMap<EClassId, &Vec<ENode>>>();
+
+            let mut placeholders = HashMap::new();
+            let mut matches = vec![];
+            for (id, class) in &classes {
+                for node in class {
+                    for instance in self.instances(node, &nodes, &mut placeholders) {
+                        for transformation in TRANSFORMATIONS {
+                            let rewritten = transformation(&instance);
+                            if rewritten != instance {
+                                matches.push((*id, rewritten));
+                            }
+                        }
+                    }
+                }
+            }
+
+            let mut changed = false;
+            for (id, rewritten) in matches {
+                let rewritten = self.add_instance(&rewritten, &placeholders);
+                changed |= self.union(id, rewritten);
+
+                if self.memo.len() >= node_limit {
+                    break;
+                }
+            }
+
+            self.rebuild();
+
+            if !changed {
+                return true;
+            }
+
+            if self.memo.len() >= node_limit {
+                return false;
+            }
+        }
+
+        false
+    }
+}
+
+impl MathType {
+    pub fn simplify_egraph(&self, cost: &CostFunction) -> MathType {
+        self.try_simplify_egraph(cost).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    //Unlike simplify this can not oscillate, every form found stays available to the extraction
+    pub fn try_simplify_egraph(&self, cost: &CostFunction) -> Result<MathType, MathError> {
+        self.validate()?;
+
+        let mut graph = EGraph::new();
+        let root = graph.add_tree(self);
+        graph.saturate(30, 2_000);
+
+        let mut result = graph.extract(root, cost).unwrap_or_else(|| self.clone());
+        result.sort();
+
+        Ok(result)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> MathType {
+        MathType::parse(input).unwrap()
+    }
+
+    #[test]
+    fn expand_and_factor_out_saturate() {
+        //Applied one after another the two transformations go back and forth forever
+        let expanded = parse("a * b + a * c");
+        let factored = expanded.factor_out();
+        assert!(factored != expanded && factored.expand().flatten() == expanded);
+
+        let mut graph = EGraph::new();
+        let root = graph.add_tree(&expanded);
+        assert!(graph.saturate(30, 2_000));
+
+        let factored_id = graph.add_tree(&factored);
+        assert!(graph.find(factored_id) == graph.find(root));
+        assert!(graph.extract(root, &node_count).unwrap() == factored);
+    }
+
+    #[test]
+    fn extraction_returns_the_smallest_representative() {
+        let mut graph = EGraph::new();
+        let large = graph.add_tree(&parse("(a + 0) * 1 * b"));
+        let small = graph.add_tree(&parse("a * b"));
+        graph.union(large, small);
+        graph.rebuild();
+
+        assert!(graph.extract(large, &node_count).unwrap() == parse("a * b"));
+    }
+
+    #[test]
+    fn simplifies_through_the_graph() {
+        assert!(parse("2 * a + 3 * a").simplify_egraph(&node_count) == parse("5 * a"));
+        assert!(parse("(a + b) * c - c * b").simplify_egraph(&node_count) == parse("a * c"));
+        assert!(parse("x * x * x^-1").simplify_egraph(&node_count) == parse("x"));
+        assert!(parse("exp(a * b + a * c) + 0").simplify_egraph(&node_count) == parse("exp(a * (b + c))"));
+    }
+
+    #[test]
+    fn congruent_nodes_merge_on_rebuild() {
+        let mut graph = EGraph::new();
+        let fa = graph.add_tree(&parse("f(a)"));
+        let fb = graph.add_tree(&parse("f(b)"));
+        let (a, b) = (graph.add_tree(&parse("a")), graph.add_tree(&parse("b")));
+
+        graph.union(a, b);
+        graph.rebuild();
+
+        assert!(graph.find(fa) == graph.find(fb));
+    }
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, fmt};
+
+use super::{math_type::MathType, math_type_name::MathTypeName};
+
+//Implementations for named functions like f, f' and f'', called with the evaluated parameters
+pub type NumericFunction = dyn Fn(&[f64]) -> f64;
+pub type FunctionTable = HashMap<String, Box<NumericFunction>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    UnboundVariable(String),
+    UnknownFunction(String),
+    Undefined,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::UnboundVariable(name) => write!(f, "Variable {} has no value", name),
+            EvalError::UnknownFunction(name) => write!(f, "Function {} has no implementation", name),
+            EvalError::Undefined => write!(f, "Undefined can not be evaluated"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl MathType {
+    pub fn eval(&self, env: &HashMap<String, f64>) -> Result<f64, EvalError> {
+        self.eval_with(env, &FunctionTable::new())
+    }
+
+    pub fn eval_with(&self, env: &HashMap<String, f64>, functions: &FunctionTable) -> Result<f64, EvalError> {
+        match self.type_name {
+            MathTypeName::Undefined => Err(EvalError::Undefined),
+            MathTypeName::NaturalNumber(value) => Ok(value as f64),
+            MathTypeName::Rational(value) => Ok(value.to_f64()),
+            MathTypeName::Function(ref name) => {
+                if self.parameter.is_empty() {
+                    if let Some(value) = env.get(name) {
+                        return Ok(*value);
+                    }
+
+                    if !functions.contains_key(name) {
+                        return Err(EvalError::UnboundVariable(name.clone()));
+                    }
+                }
+
+                let function = functions.get(name).ok_or_else(|| EvalError::UnknownFunction(name.clone()))?;
+
+                let mut values = vec![];
+                for para in &self.parameter {
+                    values.push(para.eval_with(env, functions)?);
+                }
+
+                Ok(function(&values))
+            },
+            MathTypeName::Sum => {
+                let mut sum = 0.0;
+                for para in &self.parameter {
+                    sum += para.eval_with(env, functions)?;
+                }
+
+                Ok(sum)
+            },
+            MathTypeName::Product => {
+                let mut product = 1.0;
+                for para in &self.parameter {
+                    product *= para.eval_with(env, functions)?;
+                }
+
+                Ok(product)
+            },
+            MathTypeName::FlipSign => Ok(-self.parameter[0].eval_with(env, functions)?),
+            MathTypeName::Exp => Ok(self.parameter[0].eval_with(env, functions)?.exp()),
+            MathTypeName::LogN => Ok(self.parameter[0].eval_with(env, functions)?.ln()),
+            MathTypeName::Power => {
+                let base = self.parameter[0].eval_with(env, functions)?;
+                let exponent = self.parameter[1].eval_with(env, functions)?;
+
+                Ok(base.powf(exponent))
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> HashMap<String, f64> {
+        HashMap::from([("x".to_string(), 2.0), ("y".to_string(), -0.5)])
+    }
+
+    fn eval(input: &str) -> Result<f64, EvalError> {
+        MathType::parse(input).unwrap().eval(&env())
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn exp_ln_and_pow_values() {
+        assert!(close(eval("exp(x)").unwrap(), 2f64.exp()));
+        assert!(close(eval("ln(x^3)").unwrap(), 3.0 * 2f64.ln()));
+        assert!(close(eval("exp(ln(x) * y)").unwrap(), 2f64.powf(-0.5)));
+        assert!(close(eval("x^y").unwrap(), 2f64.powf(-0.5)));
+        assert!(close(eval("x^(1/2) * 2^-2").unwrap(), 2f64.sqrt() / 4.0));
+        assert!(close(eval("(x - 3 * y) / 7").unwrap(), 0.5));
+        assert!(eval("ln(y)").unwrap().is_nan());
+    }
+
+    #[test]
+    fn custom_functions() {
+        let mut functions = FunctionTable::new();
+        functions.insert("f".to_string(), Box::new(|x: &[f64]| x[0] * x[0] + 1.0));
+        functions.insert("g".to_string(), Box::new(|x: &[f64]| x.iter().product()));
+        functions.insert("c".to_string(), Box::new(|_: &[f64]| 10.0));
+
+        let tree = MathType::parse("f(x + 1) * g(x, y, 3) + c").unwrap();
+        assert!(close(tree.eval_with(&env(), &functions).unwrap(), 10.0 * -3.0 + 10.0));
+
+        //A variable in env wins over a constant function with the same name
+        let mut env = env();
+        env.insert("c".to_string(), 1.0);
+        assert!(close(tree.eval_with(&env, &functions).unwrap(), 10.0 * -3.0 + 1.0));
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert_eq!(eval("x + z"), Err(EvalError::UnboundVariable("z".to_string())));
+        assert_eq!(eval("f(x) + 1"), Err(EvalError::UnknownFunction("f".to_string())));
+        assert_eq!(eval("exp(f'(z))"), Err(EvalError::UnknownFunction("f'".to_string())));
+        assert_eq!(MathType::new(MathTypeName::Undefined, vec![]).eval(&env()), Err(EvalError::Undefined));
+    }
+}
@@ -0,0 +1,745 @@
+use super::{math_error::MathError, math_type::{self, MathType}, polynomial::{MonomialOrder, Polynomial}, rational::Rational};
+
+//Moduli stay below this, so the product of two residues fits into an i128
+const MODULUS_LIMIT: i128 = 1 << 62;
+
+//Kronecker substitution gives up beyond this degree
+const KRONECKER_DEGREE_LIMIT: u64 = 1 << 12;
+
+/*
+Dense univariate polynomials over Z or Z/mZ, coefficients from the lowest degree up.
+Trailing zeros are always trimmed, so the zero polynomial is empty.
+ */
+type Dense = Vec<i128>;
+
+fn trim(mut a: Dense) -> Dense {
+    while a.last() == Some(&0) {
+        a.pop();
+    }
+
+    a
+}
+
+fn degree(a: &[i128]) -> usize {
+    a.len().saturating_sub(1)
+}
+
+fn reduce(a: &[i128], m: i128) -> Dense {
+    trim(a.iter().map(|x| x.rem_euclid(m)).collect())
+}
+
+//Inverse of a modulo m, gcd(a, m) has to be 1
+fn inverse(a: i128, m: i128) -> i128 {
+    let (mut r0, mut r1) = (a.rem_euclid(m), m);
+    let (mut s0, mut s1) = (1_i128, 0_i128);
+
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (s0, s1) = (s1, s0 - q * s1);
+    }
+
+    s0.rem_euclid(m)
+}
+
+fn add_mod(a: &[i128], b: &[i128], m: i128) -> Dense {
+    let mut result = vec![0; a.len().max(b.len())];
+    for (i, x) in a.iter().enumerate() {
+        result[i] = *x;
+    }
+
+    for (i, x) in b.iter().enumerate() {
+        result[i] = (result[i] + x).rem_euclid(m);
+    }
+
+    trim(result)
+}
+
+fn sub_mod(a: &[i128], b: &[i128], m: i128) -> Dense {
+    add_mod(a, &b.iter().map(|x| (-x).rem_euclid(m)).collect::<Dense>(), m)
+}
+
+fn scale_mod(a: &[i128], factor: i128, m: i128) -> Dense {
+    trim(a.iter().map(|x| (x * factor).rem_euclid(m)).collect())
+}
+
+fn mul_mod(a: &[i128], b: &[i128], m: i128) -> Dense {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+
+    let mut result = vec![0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i + j] = (result[i + j] + x * y).rem_euclid(m);
+        }
+    }
+
+    trim(result)
+}
+
+//The leading coefficient of b has to be invertible modulo m
+fn div_rem_mod(a: &[i128], b: &[i128], m: i128) -> (Dense, Dense) {
+    if a.len() < b.len() {
+        return (vec![], a.to_vec());
+    }
+
+    let leading_inverse = inverse(*b.last().unwrap(), m);
+    let mut remainder = a.to_vec();
+    let mut quotient = vec![0; a.len() - b.len() + 1];
+
+    for i in (0..quotient.len()).rev() {
+        let c = (remainder[i + b.len() - 1] * leading_inverse).rem_euclid(m);
+        quotient[i] = c;
+
+        if c != 0 {
+            for (j, y) in b.iter().enumerate() {
+                remainder[i + j] = (remainder[i + j] - c * y).rem_euclid(m);
+            }
+        }
+    }
+
+    remainder.truncate(b.len() - 1);
+    (trim(quotient), trim(remainder))
+}
+
+fn monic_mod(a: &[i128], m: i128) -> Dense {
+    match a.last() {
+        Some(leading) => scale_mod(a, inverse(*leading, m), m),
+        None => vec![],
+    }
+}
+
+//Monic gcd, p has to be prime
+fn gcd_mod(a: &[i128], b: &[i128], p: i128) -> Dense {
+    let (mut a, mut b) = (a.to_vec(), b.to_vec());
+    while !b.is_empty() {
+        let remainder = div_rem_mod(&a, &b, p).1;
+        (a, b) = (b, remainder);
+    }
+
+    monic_mod(&a, p)
+}
+
+//(s, t) with s a + t b = 1 modulo the prime p, a and b have to be coprime
+fn extended_gcd_mod(a: &[i128], b: &[i128], p: i128) -> (Dense, Dense) {
+    let (mut r0, mut r1) = (a.to_vec(), b.to_vec());
+    let (mut s0, mut s1): (Dense, Dense) = (vec![1], vec![]);
+    let (mut t0, mut t1): (Dense, Dense) = (vec![], vec![1]);
+
+    while !r1.is_empty() {
+        let (q, r) = div_rem_mod(&r0, &r1, p);
+        (r0, r1) = (r1, r);
+
+        let s = sub_mod(&s0, &mul_mod(&q, &s1, p), p);
+        (s0, s1) = (s1, s);
+
+        let t = sub_mod(&t0, &mul_mod(&q, &t1, p), p);
+        (t0, t1) = (t1, t);
+    }
+
+    //r0 is a constant, scale it to 1
+    let factor = inverse(r0[0], p);
+    (scale_mod(&s0, factor, p), scale_mod(&t0, factor, p))
+}
+
+fn pow_mod(base: &[i128], mut exponent: u128, modulus: &[i128], p: i128) -> Dense {
+    let mut result = div_rem_mod(&[1], modulus, p).1;
+    let mut square = div_rem_mod(base, modulus, p).1;
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = div_rem_mod(&mul_mod(&result, &square, p), modulus, p).1;
+        }
+
+        exponent >>= 1;
+        if exponent > 0 {
+            square = div_rem_mod(&mul_mod(&square, &square, p), modulus, p).1;
+        }
+    }
+
+    result
+}
+
+fn dense_derivative(a: &[i128], m: i128) -> Dense {
+    trim(a.iter().enumerate().skip(1).map(|(i, x)| (x * i as i128).rem_euclid(m)).collect())
+}
+
+//Splits a monic square free f into (product of all factors of degree d, d)
+fn distinct_degree(f: &[i128], p: i128) -> Vec<(Dense, usize)> {
+    let x = vec![0, 1];
+    let mut result = vec![];
+    let mut f = f.to_vec();
+    let mut h = x.clone();
+    let mut d = 0;
+
+    while degree(&f) >= 2 * (d + 1) {
+        d += 1;
+        h = pow_mod(&h, p as u128, &f, p);
+
+        let g = gcd_mod(&f, &sub_mod(&h, &x, p), p);
+        if degree(&g) > 0 {
+            f = div_rem_mod(&f, &g, p).0;
+            h = div_rem_mod(&h, &f, p).1;
+            result.push((g, d));
+        }
+    }
+
+    if degree(&f) > 0 {
+        let d = degree(&f);
+        result.push((f, d));
+    }
+
+    result
+}
+
+//Cantor-Zassenhaus, g is a monic product of factors of degree d and p is an odd prime
+fn equal_degree(g: &[i128], d: usize, p: i128, seed: &mut u64, result: &mut Vec<Dense>) {
+    if degree(g) == d {
+        result.push(g.to_vec());
+        return;
+    }
+
+    loop {
+        let a = trim((0..degree(g)).map(|_| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            (*seed % p as u64) as i128
+        }).collect());
+
+        if degree(&a) == 0 {
+            continue;
+        }
+
+        //a^((p^d - 1) / 2) = (a^(1 + p + ... + p^(d - 1)))^((p - 1) / 2)
+        let mut power = a.clone();
+        let mut frobenius = a.clone();
+        for _ in 1..d {
+            frobenius = pow_mod(&frobenius, p as u128, g, p);
+            power = div_rem_mod(&mul_mod(&power, &frobenius, p), g, p).1;
+        }
+
+        let power = pow_mod(&power, ((p - 1) / 2) as u128, g, p);
+        let split = gcd_mod(g, &sub_mod(&power, &[1], p), p);
+
+        if degree(&split) > 0 && degree(&split) < degree(g) {
+            let rest = div_rem_mod(g, &split, p).0;
+            equal_degree(&split, d, p, seed, result);
+            equal_degree(&rest, d, p, seed, result);
+            return;
+        }
+    }
+}
+
+fn is_prime(n: i128) -> bool {
+    n >= 2 && (2..).take_while(|x| x * x <= n).all(|x| n % x != 0)
+}
+
+//Odd prime that keeps the degree of f and leaves it square free
+fn choose_prime(f: &[i128]) -> Option<i128> {
+    (3..1 << 16).filter(|x| is_prime(*x)).find(|p| {
+        let reduced = reduce(f, *p);
+        degree(&reduced) == degree(f) && degree(&gcd_mod(&reduced, &dense_derivative(&reduced, *p), *p)) == 0
+    })
+}
+
+/*
+Lifts f = g h (mod p) to f = g h (mod p^k) with g monic.
+Every step solves g b + h a = (f - g h) / m (mod p) with deg a < deg g.
+ */
+fn hensel_pair(f: &[i128], g: &[i128], h: &[i128], p: i128, k: u32) -> (Dense, Dense) {
+    let (s, t) = extended_gcd_mod(g, h, p);
+    let (mut g, mut h) = (g.to_vec(), h.to_vec());
+    let mut m = p;
+
+    for _ in 1..k {
+        let next = m * p;
+        let error = sub_mod(&reduce(f, next), &mul_mod(&g, &h, next), next);
+        let error = reduce(&error.iter().map(|x| x / m).collect::<Dense>(), p);
+
+        let (q, a) = div_rem_mod(&mul_mod(&error, &t, p), &g, p);
+        let b = add_mod(&mul_mod(&error, &s, p), &mul_mod(&q, &h, p), p);
+
+        g = add_mod(&g, &scale_mod(&a, m, next), next);
+        h = add_mod(&h, &scale_mod(&b, m, next), next);
+        m = next;
+    }
+
+    (g, h)
+}
+
+//Monic factors of f modulo p^k from the monic factors modulo p
+fn hensel_lift(f: &[i128], factors: &[Dense], p: i128, k: u32) -> Vec<Dense> {
+    let modulus = p.pow(k);
+    let mut result = vec![];
+    let mut rest = reduce(f, modulus);
+
+    for i in 0..factors.len() - 1 {
+        let mut cofactor = vec![rest.last().unwrap().rem_euclid(p)];
+        for factor in &factors[i + 1..] {
+            cofactor = mul_mod(&cofactor, factor, p);
+        }
+
+        let (g, h) = hensel_pair(&rest, &factors[i], &cofactor, p, k);
+        result.push(g);
+        rest = h;
+    }
+
+    result.push(monic_mod(&rest, modulus));
+    result
+}
+
+fn symmetric(a: &[i128], m: i128) -> Dense {
+    trim(a.iter().map(|x| if *x > m / 2 { x - m } else { *x }).collect())
+}
+
+fn primitive(a: &[i128]) -> Dense {
+    let divisor = a.iter().fold(0_u128, |acc, x| super::rational::gcd(acc, x.unsigned_abs())) as i128;
+    let sign = if a.last().is_some_and(|x| *x < 0) { -1 } else { 1 };
+
+    a.iter().map(|x| x / divisor * sign).collect()
+}
+
+//Quotient if b divides a over the integers
+fn exact_div_integer(a: &[i128], b: &[i128]) -> Option<Dense> {
+    if a.len() < b.len() {
+        return None;
+    }
+
+    let leading = *b.last().unwrap();
+    let mut remainder = a.to_vec();
+    let mut quotient = vec![0; a.len() - b.len() + 1];
+
+    for i in (0..quotient.len()).rev() {
+        let top = remainder[i + b.len() - 1];
+        if top % leading != 0 {
+            return None;
+        }
+
+        let c = top / leading;
+        quotient[i] = c;
+
+        for (j, y) in b.iter().enumerate() {
+            remainder[i + j] = remainder[i + j].checked_sub(c.checked_mul(*y)?)?;
+        }
+    }
+
+    remainder.iter().all(|x| *x == 0).then(|| trim(quotient))
+}
+
+//Next subset of the given size in lexicographic order
+fn next_subset(subset: &mut [usize], n: usize) -> bool {
+    let k = subset.len();
+    for i in (0..k).rev() {
+        if subset[i] < n - k + i {
+            subset[i] += 1;
+            for j in i + 1..k {
+                subset[j] = subset[j - 1] + 1;
+            }
+
+            return true;
+        }
+    }
+
+    false
+}
+
+//lc(f) times the product of the chosen lifted factors, as a primitive integer polynomial
+fn lifted_product(f: &[i128], lifted: &[Dense], chosen: &[usize], modulus: i128) -> Dense {
+    let mut result = vec![f.last().unwrap().rem_euclid(modulus)];
+    for i in chosen {
+        result = mul_mod(&result, &lifted[*i], modulus);
+    }
+
+    primitive(&symmetric(&result, modulus))
+}
+
+/*
+Zassenhaus recombination: smallest subsets of lifted factors whose product divides f over the integers.
+Of a subset and its complement only the side with the lower degree is tested,
+so the modulus only has to bound factors of half the degree.
+ */
+fn recombine(f: &[i128], mut lifted: Vec<Dense>, modulus: i128) -> Vec<Dense> {
+    let mut f = f.to_vec();
+    let mut result = vec![];
+    let mut size = 1;
+
+    'sizes: while 2 * size <= lifted.len() {
+        let mut subset = (0..size).collect::<Vec<usize>>();
+
+        loop {
+            let subset_degree = subset.iter().map(|i| degree(&lifted[*i])).sum::<usize>();
+
+            let found = if 2 * subset_degree <= degree(&f) {
+                let candidate = lifted_product(&f, &lifted, &subset, modulus);
+                exact_div_integer(&f, &candidate).map(|quotient| (candidate, quotient))
+            } else {
+                let complement = (0..lifted.len()).filter(|x| !subset.contains(x)).collect::<Vec<usize>>();
+                let candidate = lifted_product(&f, &lifted, &complement, modulus);
+                exact_div_integer(&f, &candidate).map(|quotient| (primitive(&quotient), candidate))
+            };
+
+            if let Some((factor, quotient)) = found {
+                result.push(factor);
+                f = quotient;
+
+                for i in subset.iter().rev() {
+                    lifted.remove(*i);
+                }
+
+                continue 'sizes;
+            }
+
+            if !next_subset(&mut subset, lifted.len()) {
+                break;
+            }
+        }
+
+        size += 1;
+    }
+
+    if degree(&f) > 0 {
+        result.push(primitive(&f));
+    }
+
+    result
+}
+
+//Irreducible factors of a primitive square free f with positive degree
+fn factor_dense(f: &[i128]) -> Result<Vec<Dense>, MathError> {
+    if degree(f) == 1 {
+        return Ok(vec![f.to_vec()]);
+    }
+
+    let p = choose_prime(f).ok_or_else(|| MathError::Unsupported("no prime keeps the polynomial square free".to_string()))?;
+
+    let mut factors = vec![];
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    for (g, d) in distinct_degree(&monic_mod(&reduce(f, p), p), p) {
+        equal_degree(&g, d, p, &mut seed, &mut factors);
+    }
+
+    if factors.len() == 1 {
+        return Ok(vec![f.to_vec()]);
+    }
+
+    //Coefficients of factors of degree m are bounded by 2^m |f|_2, recombine only tests m <= n / 2
+    let norm = f.iter().map(|x| (*x as f64) * (*x as f64)).sum::<f64>().sqrt();
+    let bound = 2.0 * ((degree(f) / 2) as f64).exp2() * norm * (*f.last().unwrap() as f64).abs();
+
+    let mut k = 1;
+    while (p as f64).powi(k as i32) <= bound {
+        k += 1;
+    }
+
+    if (p as f64).powi(k as i32) >= MODULUS_LIMIT as f64 {
+        return Err(MathError::Overflow);
+    }
+
+    let lifted = hensel_lift(f, &factors, p, k);
+    Ok(recombine(f, lifted, p.pow(k)))
+}
+
+fn to_dense(poly: &Polynomial, index: usize) -> Result<Dense, MathError> {
+    let mut result = vec![0; poly.degree(index).unwrap_or(0) as usize + 1];
+    for (exponents, coefficient) in poly.terms() {
+        if !coefficient.is_integer() {
+            return Err(MathError::Unsupported(format!("non integer coefficient {}", coefficient)));
+        }
+
+        result[exponents[index] as usize] = coefficient.numerator();
+    }
+
+    Ok(trim(result))
+}
+
+fn from_dense(dense: &[i128], variables: &[MathType], index: usize) -> Result<Polynomial, MathError> {
+    let mut result = Polynomial::zero(variables);
+    for (i, coefficient) in dense.iter().enumerate() {
+        let mut exponents = vec![0; variables.len()];
+        exponents[index] = i as u32;
+        result = result.try_add(&Polynomial::monomial(variables, exponents, Rational::try_integer(*coefficient)?))?;
+    }
+
+    Ok(result)
+}
+
+//Yun's algorithm in variables[index], poly has to be primitive in that variable
+fn square_free(poly: &Polynomial, index: usize) -> Result<Vec<(Polynomial, u32)>, MathError> {
+    let derivative = poly.derivative(index)?;
+    let divisor = poly.gcd(&derivative)?;
+
+    let mut c = poly.exact_div(&divisor)?;
+    let mut d = derivative.exact_div(&divisor)?.try_sub(&c.derivative(index)?)?;
+    let mut multiplicity = 1;
+    let mut result = vec![];
+
+    while c.degree(index).unwrap_or(0) > 0 {
+        let a = c.gcd(&d)?;
+        c = c.exact_div(&a)?;
+        d = d.exact_div(&a)?.try_sub(&c.derivative(index)?)?;
+
+        if a.degree(index).unwrap_or(0) > 0 {
+            result.push((a, multiplicity));
+        }
+
+        multiplicity += 1;
+    }
+
+    Ok(result)
+}
+
+/*
+Kronecker substitution x_i = t^(D^i) with D above every degree,
+the univariate factors are recombined and mapped back until they divide poly.
+ */
+fn factor_kronecker(poly: &Polynomial, used: &[usize]) -> Result<Vec<Polynomial>, MathError> {
+    let base = used.iter().map(|i| poly.degree(*i).unwrap_or(0) as u64).max().unwrap_or(0) + 1;
+
+    let mut total_degree = 0_u64;
+    for (position, i) in used.iter().enumerate() {
+        let weight = base.checked_pow(position as u32).ok_or(MathError::Overflow)?;
+        let degree = (poly.degree(*i).unwrap_or(0) as u64).checked_mul(weight).ok_or(MathError::Overflow)?;
+        total_degree = total_degree.checked_add(degree).ok_or(MathError::Overflow)?;
+    }
+
+    if total_degree > KRONECKER_DEGREE_LIMIT {
+        return Err(MathError::Unsupported(format!("Kronecker substitution of degree {}", total_degree)));
+    }
+
+    let t = [math_type::variable("t")];
+    let mut substituted = Polynomial::zero(&t);
+    for (exponents, coefficient) in poly.terms() {
+        let mut degree = 0;
+        for (position, i) in used.iter().enumerate() {
+            degree += exponents[*i] as u64 * base.pow(position as u32);
+        }
+
+        substituted = substituted.try_add(&Polynomial::monomial(&t, vec![degree as u32], *coefficient))?;
+    }
+
+    let mut pieces = vec![];
+    for (factor, multiplicity) in factor_polynomial(&substituted)? {
+        for _ in 0..multiplicity {
+            pieces.push(factor.clone());
+        }
+    }
+
+    let mut rest = poly.clone();
+    let mut result = vec![];
+    let mut size = 1;
+
+    'sizes: while 2 * size <= pieces.len() {
+        let mut subset = (0..size).collect::<Vec<usize>>();
+
+        loop {
+            let mut image = Polynomial::constant(&t, Rational::ONE);
+            for i in &subset {
+                image = image.try_mul(&pieces[*i])?;
+            }
+
+            let mut candidate = Polynomial::zero(poly.variables());
+            for (exponents, coefficient) in image.terms() {
+                let mut degree = exponents[0] as u64;
+                let mut new_exponents = vec![0; poly.variables().len()];
+                for i in used {
+                    new_exponents[*i] = (degree % base) as u32;
+                    degree /= base;
+                }
+
+                candidate = candidate.try_add(&Polynomial::monomial(poly.variables(), new_exponents, *coefficient))?;
+            }
+
+            if candidate.total_degree().unwrap_or(0) > 0 {
+                let (quotient, remainder) = rest.div_rem(&candidate, MonomialOrder::Lex)?;
+                if remainder.is_zero() {
+                    result.push(candidate.normalized()?);
+                    rest = quotient;
+
+                    for i in subset.iter().rev() {
+                        pieces.remove(*i);
+                    }
+
+                    continue 'sizes;
+                }
+            }
+
+            if !next_subset(&mut subset, pieces.len()) {
+                break;
+            }
+        }
+
+        size += 1;
+    }
+
+    if rest.total_degree().unwrap_or(0) > 0 {
+        result.push(rest.normalized()?);
+    }
+
+    Ok(result)
+}
+
+fn factor_square_free(poly: &Polynomial) -> Result<Vec<Polynomial>, MathError> {
+    let poly = poly.normalized()?;
+    let used = (0..poly.variables().len()).filter(|i| poly.degree(*i).unwrap_or(0) > 0).collect::<Vec<usize>>();
+
+    if used.len() == 1 {
+        let mut result = vec![];
+        for factor in factor_dense(&to_dense(&poly, used[0])?)? {
+            result.push(from_dense(&factor, poly.variables(), used[0])?.normalized()?);
+        }
+
+        return Ok(result);
+    }
+
+    factor_kronecker(&poly, &used)
+}
+
+//Irreducible factors with multiplicities of a polynomial with integer coefficients, constants are dropped
+fn factor_polynomial(poly: &Polynomial) -> Result<Vec<(Polynomial, u32)>, MathError> {
+    let mut result = vec![];
+    let mut poly = poly.clone();
+
+    //Monomial factors first, x^2 y + x y => x y (x + 1)
+    for i in 0..poly.variables().len() {
+        let lowest = poly.terms().map(|(x, _)| x[i]).min().unwrap_or(0);
+        if lowest == 0 {
+            continue;
+        }
+
+        let mut exponents = vec![0; poly.variables().len()];
+        exponents[i] = lowest;
+        poly = poly.exact_div(&Polynomial::monomial(poly.variables(), exponents, Rational::ONE))?;
+
+        result.push((Polynomial::variable(poly.variables(), i), lowest));
+    }
+
+    let Some(index) = poly.main_variable() else {
+        return Ok(result);
+    };
+
+    let content = poly.content(index)?;
+    let primitive = poly.exact_div(&content)?;
+    result.extend(factor_polynomial(&content)?);
+
+    for (part, multiplicity) in square_free(&primitive, index)? {
+        for factor in factor_square_free(&part)? {
+            result.push((factor, multiplicity));
+        }
+    }
+
+    Ok(result)
+}
+
+impl MathType {
+    pub fn factor(&self) -> MathType {
+        self.try_factor().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /*
+    Factors the polynomial over the integers, every atom that is not a number counts as a variable.
+    The result is a constant times irreducible factors, repeated factors become powers.
+    Overflow if the coefficients get too large for the modular arithmetic,
+    Unsupported if no prime fits or the multivariate degrees exceed the Kronecker substitution limit.
+
+    x^2 - y^2 => (x + y) (x - y)
+    2 x^3 + 4 x^2 + 2 x => 2 x (x + 1)^2
+     */
+    pub fn try_factor(&self) -> Result<MathType, MathError> {
+        self.validate()?;
+
+        let Some(poly) = self.to_polynomial(&[]) else {
+            return Ok(self.clone());
+        };
+
+        if poly.constant_value().is_some() {
+            return Ok(poly.to_math_type());
+        }
+
+        let mut factors: Vec<(Polynomial, u32)> = vec![];
+        for (factor, multiplicity) in factor_polynomial(&poly.normalized()?)? {
+            match factors.iter_mut().find(|x| x.0 == factor) {
+                Some(existing) => existing.1 += multiplicity,
+                None => factors.push((factor, multiplicity)),
+            }
+        }
+
+        //poly = constant * product of the normalized factors, compared by leading coefficients
+        let mut leading = Rational::ONE;
+        for (factor, multiplicity) in &factors {
+            let (_, coefficient) = factor.leading_term(MonomialOrder::Lex).unwrap();
+            leading = leading.checked_mul(coefficient.pow(*multiplicity as i32)?).ok_or(MathError::Overflow)?;
+        }
+
+        let (_, poly_leading) = poly.leading_term(MonomialOrder::Lex).unwrap();
+        let constant = poly_leading.checked_div(leading).ok_or(MathError::Overflow)?;
+
+        let mut parameter = vec![];
+        for (factor, multiplicity) in factors {
+            parameter.push(match multiplicity {
+                1 => factor.to_math_type(),
+                _ => math_type::power(factor.to_math_type(), math_type::natural_number(multiplicity as u128)),
+            });
+        }
+
+        let rest = match parameter.len() {
+            1 => parameter.remove(0),
+            _ => math_type::product(parameter),
+        };
+
+        let mut result = math_type::with_coefficient(constant, rest);
+        result.sort();
+
+        Ok(result)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polynomial(input: &str) -> Polynomial {
+        let x = [math_type::variable("x")];
+        MathType::parse(input).unwrap().to_polynomial(&x).unwrap()
+    }
+
+    fn factor(input: &str) -> String {
+        MathType::parse(input).unwrap().factor().get_typst_string()
+    }
+
+    #[test]
+    fn square_free_decomposition() {
+        let parts = square_free(&polynomial("(x + 1) * (x - 2)^2 * (x^2 + 1)^2 * (x + 3)^4"), 0).unwrap();
+
+        assert!(parts.len() == 3);
+        assert!(parts[0].0 == polynomial("x + 1") && parts[0].1 == 1);
+        assert!(parts[1].0 == polynomial("(x - 2) * (x^2 + 1)") && parts[1].1 == 2);
+        assert!(parts[2].0 == polynomial("x + 3") && parts[2].1 == 4);
+    }
+
+    #[test]
+    fn difference_of_squares() {
+        assert_eq!(factor("x^2 - y^2"), "(x + y) (x + -y)");
+        assert_eq!(factor("4 * x^2 - 9"), "(3 + 2 x) (-3 + 2 x)");
+    }
+
+    #[test]
+    fn repeated_and_monomial_factors() {
+        assert_eq!(factor("2 * x^3 + 4 * x^2 + 2 * x"), "2 x (1 + x)^(2)");
+        assert_eq!(factor("x^4 - 1"), "(1 + x) (1 + (x)^(2)) (-1 + x)");
+        assert_eq!(factor("x / 2 + 1 / 3"), "frac(1, 6) (2 + 3 x)");
+    }
+
+    #[test]
+    fn irreducible_stays() {
+        assert_eq!(factor("x^2 + x + 1"), "1 + x + (x)^(2)");
+    }
+
+    #[test]
+    fn kronecker_limit_is_unsupported() {
+        let result = MathType::parse("x^70 * y^70 + 1").unwrap().try_factor();
+        assert!(matches!(result, Err(MathError::Unsupported(_))));
+    }
+}
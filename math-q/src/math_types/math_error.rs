@@ -0,0 +1,51 @@
+use std::fmt;
+
+use super::{math_type_name::MathTypeName, parser::ParseError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathError {
+    ArityMismatch {
+        type_name: MathTypeName,
+        expected: (usize, usize),
+        found: usize,
+    },
+    //Derivatives can only be taken with respect to variables, holds the string of the target
+    NonVariableDerivative(String),
+    //The right side of a rewrite rule uses a pattern variable the left side does not bind
+    UnboundPatternVariable(String),
+    Overflow,
+    DivisionByZero,
+    //The input is valid but outside of what the algorithm handles, holds the reason
+    Unsupported(String),
+    Parse(ParseError),
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::ArityMismatch { type_name, expected: (min, max), found } => {
+                if min == max {
+                    write!(f, "{:?} takes {} parameters, found {}", type_name, min, found)
+                } else if *max == usize::MAX {
+                    write!(f, "{:?} takes at least {} parameters, found {}", type_name, min, found)
+                } else {
+                    write!(f, "{:?} takes {} to {} parameters, found {}", type_name, min, max, found)
+                }
+            },
+            MathError::NonVariableDerivative(target) => write!(f, "Derivative can only be calculated for variables, found {}", target),
+            MathError::UnboundPatternVariable(name) => write!(f, "Pattern variable {} is not bound by the left side", name),
+            MathError::Overflow => write!(f, "Arithmetic overflow"),
+            MathError::DivisionByZero => write!(f, "Division by zero"),
+            MathError::Unsupported(reason) => write!(f, "Unsupported: {}", reason),
+            MathError::Parse(error) => write!(f, "Parse error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for MathError {}
+
+impl From<ParseError> for MathError {
+    fn from(error: ParseError) -> Self {
+        MathError::Parse(error)
+    }
+}
@@ -1,9 +1,9 @@
 use core::panic;
-use std::{char::ParseCharError, cmp::Ordering, fmt::Error, iter::Sum};
+use std::{char::ParseCharError, cmp::Ordering, collections::{HashMap, HashSet}, fmt::Error, hash::{DefaultHasher, Hash, Hasher}, iter::Sum};
 
 use crate::math_types::{math_type, typst_symbols};
 
-use super::math_type_name::MathTypeName;
+use super::{dag::{ExprId, ExprPool}, math_error::MathError, math_type_name::MathTypeName, rational::Rational};
 
 
 #[derive(Clone, Eq)]
@@ -13,72 +13,78 @@ pub struct MathType {
 }
 
 //Type, Parameter count, Parameters cmp
-impl PartialOrd for MathType {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+//Parameters of commutative nodes are compared in sorted order, so a + b == b + a without calling sort()
+impl Ord for MathType {
+    fn cmp(&self, other: &Self) -> Ordering {
         let name_order = self.type_name.cmp(&other.type_name);
         if name_order != Ordering::Equal {
-            return Some(name_order);
+            return name_order;
         }
 
         if self.parameter.len() != other.parameter.len() {
-            return Some(self.parameter.len().cmp(&other.parameter.len()));
+            return self.parameter.len().cmp(&other.parameter.len());
         }
 
-        
         if self.type_name.is_commutative() {
-            //Check if the parameters are sorted
-
-            for i in 1..self.parameter.len() {
-                let cmp = self.parameter[i].partial_cmp(&self.parameter[i - 1]);    
+            let mut left = self.parameter.iter().collect::<Vec<&MathType>>();
+            let mut right = other.parameter.iter().collect::<Vec<&MathType>>();
+            left.sort();
+            right.sort();
 
-                if let Some(res) = cmp {
-                    if res == Ordering::Less {
-                        return None;
-                    }
-                }
-                else {
-                    return None;
-                }
-
-                let cmp = other.parameter[i].partial_cmp(&other.parameter[i - 1]);    
-
-                if let Some(res) = cmp {
-                    if res == Ordering::Less {
-                        return None;
-                    }
-                }
-                else {
-                    return None;
-                }
-            }
+            return left.cmp(&right);
         }
 
-        for i in 0..self.parameter.len() {
-            let cmp = self.parameter[i].partial_cmp(&other.parameter[i]);    
-
-            if let Some(res) = cmp {
-                if res != Ordering::Equal {
-                    return Some(res);
-                }
-            }
-            else {
-                return None;
-            }
-        }        
+        self.parameter.cmp(&other.parameter)
+    }
+}
 
-        Some(Ordering::Equal)
+impl PartialOrd for MathType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl PartialEq for MathType {
     fn eq(&self, other: &Self) -> bool {
-        self.partial_cmp(other) == Some(Ordering::Equal)
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+//Agrees with Eq, the parameter hashes of commutative nodes are combined independent of their order
+impl Hash for MathType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_name.hash(state);
+        self.parameter.len().hash(state);
+
+        if self.type_name.is_commutative() {
+            let mut hashes = self.parameter.iter().map(|x| {
+                let mut hasher = DefaultHasher::new();
+                x.hash(&mut hasher);
+                hasher.finish()
+            }).collect::<Vec<u64>>();
+
+            hashes.sort();
+            hashes.hash(state);
+        } else {
+            self.parameter.hash(state);
+        }
     }
 }
 
 pub fn natural_number(value: u128) -> MathType {
     MathType::new(MathTypeName::NaturalNumber(value), vec![])
 }
+//Non negative integers are stored as NaturalNumber, everything else as Rational
+pub fn rational(value: Rational) -> MathType {
+    if value.is_integer() && !value.is_negative() {
+        return natural_number(value.numerator() as u128);
+    }
+
+    MathType::new(MathTypeName::Rational(value), vec![])
+}
+pub fn integer(value: i128) -> MathType {
+    rational(Rational::integer(value))
+}
 pub fn variable(name: &str) -> MathType {
     MathType::new(MathTypeName::Function(name.to_string()), vec![])
 }
@@ -112,20 +118,52 @@ pub fn logarithm(parameter: MathType) -> MathType {
     MathType::new(MathTypeName::LogN, vec![parameter])
 }
 
+//Shared by the steps of derivative_n, ids maps the nodes of the tree that is currently derived to their pool ids
+struct DerivativeMemo {
+    pool: ExprPool,
+    ids: HashMap<*const MathType, ExprId>,
+    results: HashMap<(ExprId, ExprId), MathType>,
+}
+
 impl MathType {
     pub fn new(type_name: MathTypeName, parameter: Vec<MathType>) -> MathType {
+        MathType::try_new(type_name, parameter).unwrap_or_else(|error| panic!("{}", error))
+    }
 
+    pub fn try_new(type_name: MathTypeName, parameter: Vec<MathType>) -> Result<MathType, MathError> {
         let (min, max) = type_name.parameter_range();
 
-        // println!("{:?}: {}", type_name, parameter.len());
-
-        assert!(min <= parameter.len());
-        assert!(max >= parameter.len());
+        if parameter.len() < min || parameter.len() > max {
+            return Err(MathError::ArityMismatch {
+                type_name,
+                expected: (min, max),
+                found: parameter.len(),
+            });
+        }
 
-        MathType {
+        Ok(MathType {
             type_name,
             parameter,
+        })
+    }
+
+    //Checks the arity of every node, trees built through the public fields skip try_new
+    pub fn validate(&self) -> Result<(), MathError> {
+        let (min, max) = self.type_name.parameter_range();
+
+        if self.parameter.len() < min || self.parameter.len() > max {
+            return Err(MathError::ArityMismatch {
+                type_name: self.type_name.clone(),
+                expected: (min, max),
+                found: self.parameter.len(),
+            });
         }
+
+        for para in &self.parameter {
+            para.validate()?;
+        }
+
+        Ok(())
     }
 
     pub fn sort(&mut self) {
@@ -134,7 +172,7 @@ impl MathType {
         }
 
         if self.type_name.is_commutative() {
-            self.parameter.sort_by(|a, b| a.partial_cmp(b).expect(format!("Comparison error on sorting parameters [{}] and [{}]", a.get_typst_string(), b.get_typst_string()).as_str()));
+            self.parameter.sort();
         }
     }
 
@@ -160,6 +198,77 @@ impl MathType {
         return matches!(self.type_name, MathTypeName::Function(_)) && self.parameter.len() == 0;
     }
 
+    pub fn constant_value(&self) -> Option<Rational> {
+        match self.type_name {
+            MathTypeName::NaturalNumber(value) => Rational::try_from(value).ok(),
+            MathTypeName::Rational(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /*
+    2 * a * b => (2, a * b)
+    -a => (-1, a)
+    3 => (3, product[])
+     */
+    pub fn split_coefficient(&self) -> (Rational, MathType) {
+        if let Some(value) = self.constant_value() {
+            return (value, product(vec![]));
+        }
+
+        match self.type_name {
+            MathTypeName::FlipSign => {
+                let (coefficient, rest) = self.parameter[0].split_coefficient();
+                (-coefficient, rest)
+            },
+            MathTypeName::Product => {
+                let mut coefficient = Rational::ONE;
+                let mut rest = vec![];
+
+                for para in &self.parameter {
+                    match para.constant_value().and_then(|value| coefficient.checked_mul(value)) {
+                        Some(next) => coefficient = next,
+                        None => rest.push(para.clone()),
+                    }
+                }
+
+                if rest.len() == 1 {
+                    return (coefficient, rest.remove(0));
+                }
+
+                (coefficient, product(rest))
+            },
+            _ => (Rational::ONE, self.clone()),
+        }
+    }
+
+    /*
+    a^2 => (a, 2)
+    a => (a, 1)
+     */
+    pub fn split_exponent(&self) -> (MathType, MathType) {
+        if self.type_name == MathTypeName::Power {
+            return (self.parameter[0].clone(), self.parameter[1].clone());
+        }
+
+        (self.clone(), natural_number(1))
+    }
+
+    //a^-2 => a^2, a^-1 => a, None if the exponent is not a negative constant
+    fn inverted_power(&self) -> Option<MathType> {
+        if self.type_name != MathTypeName::Power {
+            return None;
+        }
+
+        //reciprocal stores the exponent as -(1)
+        let exponent = self.parameter[1].reduce_neutral().constant_value().filter(|x| x.is_negative())?.checked_neg()?;
+        if exponent.is_one() {
+            return Some(self.parameter[0].clone());
+        }
+
+        Some(power(self.parameter[0].clone(), rational(exponent)))
+    }
+
     pub fn contains(&self, tree: &MathType) -> bool {
         if *self == *tree {
             return true;
@@ -174,10 +283,11 @@ impl MathType {
         return false;
     }
 
-    pub fn count_nodes(&self) -> u64 {
+    //Nodes of the expanded tree, count_nodes also reports the distinct subtrees
+    pub fn tree_size(&self) -> u64 {
         let mut count = 1;
         for i in 0..self.parameter.len() {
-            count += self.parameter[i].count_nodes();
+            count += self.parameter[i].tree_size();
         }
 
         return count;
@@ -191,204 +301,162 @@ impl MathType {
         return MathType::new(self.type_name.clone(), self.parameter.iter().map(|x| x.replace(tree, replacement)).collect());
     }
 
-    pub fn simplify(&self) -> MathType {
-        let mut i = 0;
-        let mut prev = self.clone();
-        loop {
-            let mut next = prev.expand().flatten().reduce_neutral().combine();
-            next.sort();
-            
-            if next == prev {
-                return next;
-            }
-    
-            
-            i += 1;
-            
-            if i > 90 {
-                println!("Failed to simplify: \n{}", prev.get_string());
-                println!("{}", next.get_string());
-
-                println!("EQ: {}", next == prev);
-                return prev;
-            }
-            
-            prev = next.clone();
-            // println!("Finished reduction {} -> {} nodes", i, next.count_nodes());
-        } 
-    }
-
-    pub fn factor_out(&self) -> MathType {
-        println!("Factoring out: {:?} {}", self.type_name, self.parameter.len());
+    /*
+    Replaces every subtree that is a key of replacements in one top down pass.
+    Replaced subtrees are not searched again, so all replacements happen at once:
 
-        if self.type_name != MathTypeName::Sum {
-            return MathType::new(self.type_name.clone(), self.parameter.iter().map(|x| x.factor_out()).collect());
+    x + y with {x: y, y: x} => y + x
+     */
+    pub fn substitute(&self, replacements: &HashMap<MathType, MathType>) -> MathType {
+        if let Some(replacement) = replacements.get(self) {
+            return replacement.clone();
         }
 
-        let mut best_sum_selection: u64 = 0;
-        let mut best_factor_selection: u64 = 0;
-        let mut best_count = 0;
-        
-        for summand_selection in 0..(1_u64 << self.parameter.len()) {
-            if summand_selection.count_ones() <= 1 {
-                continue;
-            }
-            
-            let max_product_length = iterate_set_bits(summand_selection).map(|i| {
-                let para = &self.parameter[i as usize];
-                if para.type_name == MathTypeName::Product {
-                    para.parameter.len()
-                } else {
-                    1
-                }
-            }).min().unwrap();
+        MathType::new(self.type_name.clone(), self.parameter.iter().map(|x| x.substitute(replacements)).collect())
+    }
+
+    /*
+    Inserts the known values, folds every subtree that became a number and removes the neutral elements left behind,
+    the rest stays symbolic:
 
+    x^2 + y with {x: 3} => 9 + y
+    f(x) * exp(x - 3) with {x: 3} => f(3)
+    x / (x - 3) with {x: 3} => DivisionByZero
+     */
+    pub fn partial_eval(&self, values: &HashMap<String, Rational>) -> MathType {
+        self.try_partial_eval(values).unwrap_or_else(|error| panic!("{}", error))
+    }
 
+    pub fn try_partial_eval(&self, values: &HashMap<String, Rational>) -> Result<MathType, MathError> {
+        let replacements = values.iter().map(|(name, value)| (variable(name), rational(*value))).collect();
 
-            if max_product_length as u32 * summand_selection.count_ones() < best_count {
-                continue;
+        //reduce_neutral can turn subtrees like 0 * y into numbers, so fold until nothing changes
+        let mut prev = self.substitute(&replacements);
+        loop {
+            let next = prev.try_fold_constants()?.reduce_neutral();
+            if next == prev {
+                return Ok(next);
             }
-            
-            let first_summand = self.parameter[iterate_set_bits(summand_selection).next().unwrap() as usize].clone();
-            let first_summand_length = if first_summand.type_name == MathTypeName::Product {
-                first_summand.parameter.len()
-            } else {
-                1
-            };
-
-            for factor_selection in 0..(1_u64 << first_summand_length) {
-                if factor_selection.count_ones() == 0 || factor_selection.count_ones() > max_product_length as u32 {
-                    continue;
-                }
 
-                if best_count > summand_selection.count_ones() * factor_selection.count_ones() {
-                    continue;
-                }
+            prev = next;
+        }
+    }
 
-                // println!("Checking sum selection: {:b}, factor selection: {:b}", summand_selection, factor_selection);
+    //Bottom up, results that overflow or are not rational keep their symbolic form
+    fn try_fold_constants(&self) -> Result<MathType, MathError> {
+        let parameter = self.parameter.iter().map(|x| x.try_fold_constants()).collect::<Result<Vec<MathType>, MathError>>()?;
+        let tree = MathType::new(self.type_name.clone(), parameter);
+        let values = tree.parameter.iter().map(|x| x.constant_value()).collect::<Option<Vec<Rational>>>();
 
-                if check_selection(summand_selection, factor_selection, &self.parameter) {
-                    best_sum_selection = summand_selection;
-                    best_factor_selection = factor_selection;
+        let Some(values) = values else {
+            return Ok(tree);
+        };
 
-                    best_count = summand_selection.count_ones() * factor_selection.count_ones();
+        let folded = match tree.type_name {
+            MathTypeName::Sum => values.into_iter().try_fold(Rational::ZERO, |sum, x| sum.checked_add(x)),
+            MathTypeName::Product => values.into_iter().try_fold(Rational::ONE, |product, x| product.checked_mul(x)),
+            MathTypeName::FlipSign => values[0].checked_neg(),
+            MathTypeName::Power if values[1].is_integer() => {
+                if values[0].is_zero() && values[1].is_negative() {
+                    return Err(MathError::DivisionByZero);
                 }
 
-            }            
-        }
-
-        if best_count == 0 {
-            return self.clone();
-        }
-        // println!("Best sum selection: {:b}, best factor selection: {:b}", best_sum_selection, best_factor_selection);
-
-        let first_summand = self.parameter[iterate_set_bits(best_sum_selection).next().unwrap() as usize].clone();
-            
-        let mut factors = iterate_set_bits(best_factor_selection).map(|i| {
-            if first_summand.type_name == MathTypeName::Product {
-                return first_summand.parameter[i as usize].clone();
-            } 
+                i32::try_from(values[1].numerator()).ok().and_then(|exponent| values[0].pow(exponent).ok())
+            },
+            MathTypeName::Exp if values[0].is_zero() => Some(Rational::ONE),
+            MathTypeName::LogN if values[0].is_one() => Some(Rational::ZERO),
+            _ => None,
+        };
 
-            if i != 0 {
-                panic!("Invalid factor selection");
-            }
+        Ok(folded.map(rational).unwrap_or(tree))
+    }
 
-            return first_summand.clone();
-    
-        }).collect::<Vec<MathType>>();
+    pub fn simplify(&self) -> MathType {
+        self.try_simplify().unwrap_or_else(|error| panic!("{}", error))
+    }
 
-        let mut reduced_summands = vec![];
-        let mut other_summands = vec![];
+    pub fn try_simplify(&self) -> Result<MathType, MathError> {
+        self.validate()?;
 
-        for summand in iterate_set_bits(best_sum_selection) {
-            let current_summand = self.parameter[summand as usize].clone();
-            if current_summand.type_name != MathTypeName::Product {
-                continue;
+        let mut i = 0;
+        let mut prev = self.clone();
+        let mut best = self.clone();
+        loop {
+            let mut next = prev.expand().flatten().reduce_neutral().combine();
+            next.sort();
+            
+            if next == prev {
+                return Ok(next);
             }
 
-            let mut new_product = current_summand.parameter.clone();
-            for f in &factors {
-                for i in 0..new_product.len() {
-                    if new_product[i] == *f {
-                        new_product.remove(i);
-                        break;
-                    }               
-                }
+            if next.tree_size() < best.tree_size() {
+                best = next.clone();
             }
-
-            if new_product.len() == 0 {
-                continue;
+            
+            i += 1;
+            
+            //No fixed point, simplify_egraph can not oscillate
+            if i > 90 {
+                return Ok(best);
             }
-
-            reduced_summands.push(product(new_product));
-        }
-
-        for other_summand in iterate_set_bits(best_sum_selection ^ ((1_u64 << self.parameter.len()) - 1)) {
-            other_summands.push(self.parameter[other_summand as usize].clone());
-        }
-
-        factors.push(sum(reduced_summands));
-
-        other_summands.push(product(factors));
-
-        return sum(other_summands);
-
-        fn check_selection(summand_selection: u64, factor_selection: u64, summands: &Vec<MathType>) -> bool {
-
-            let first_summand = summands[iterate_set_bits(summand_selection).next().unwrap() as usize].clone();
             
-            let factors = iterate_set_bits(factor_selection).map(|i| {
-                if first_summand.type_name == MathTypeName::Product {
-                    return first_summand.parameter[i as usize].clone();
-                } 
+            prev = next;
+        } 
+    }
 
-                if i != 0 {
-                    panic!("Invalid factor selection");
-                }
+    /*
+    Greedy: pulls out the factor shared by the most summands, then repeats on the summands without it.
+    The remaining sums are factored recursively.
 
-                return first_summand.clone();
-        
-            }).collect::<Vec<MathType>>();
+    a b + a c + d => d + a (b + c)
+    x y + x z + x + y w => y w + x (y + z + 1)
+    a + 2 a => 3 a
+     */
+    pub fn factor_out(&self) -> MathType {
+        let para = self.parameter.iter().map(|x| x.factor_out()).collect::<Vec<MathType>>();
 
+        if self.type_name != MathTypeName::Sum {
+            return MathType::new(self.type_name.clone(), para);
+        }
 
-            for summand in iterate_set_bits(summand_selection) {
-                let current_summand = summands[summand as usize].clone();
-                if current_summand.type_name != MathTypeName::Product {
-                    return factors[0] == current_summand;
+        let mut remaining = para;
+        let mut factored = vec![];
+
+        while let Some(factor) = most_frequent_factor(&remaining) {
+            let mut reduced_summands = vec![];
+            let mut other_summands = vec![];
+
+            for summand in remaining {
+                let mut factors = summand_factors(&summand);
+                match factors.iter().position(|x| *x == factor) {
+                    Some(index) => {
+                        factors.remove(index);
+                        reduced_summands.push(match factors.len() {
+                            0 => natural_number(1),
+                            1 => factors.remove(0),
+                            _ => product(factors),
+                        });
+                    },
+                    None => other_summands.push(summand),
                 }
+            }
 
-                let mut index = 0;
-
-                for factor in &factors {
-                    while index < current_summand.parameter.len() {
-                        if current_summand.parameter[index] == *factor {
-                            break;
-                        }
-                        index += 1;
-                    }
-
-                    if index > current_summand.parameter.len() {
-                        return false;
-                    }
-
-                    index += 1;
-                }
+            let inner = sum(fold_constant_summands(reduced_summands)).factor_out();
+            match inner.constant_value() {
+                Some(value) if value.is_zero() => (),
+                Some(value) => factored.push(with_coefficient(value, factor)),
+                None => factored.push(product(vec![factor, inner])),
             }
 
-            return true;
+            remaining = other_summands;
         }
 
-        fn get_all_selections(list: &mut Vec<usize>, min_index: usize, n: usize, k: usize, result: &mut Vec<Vec<usize>>) {
-            if list.len() == k {
-                result.push(list.clone());
-                return;
-            }
+        remaining.extend(factored);
 
-            for i in min_index..n {
-                list.push(i);
-                get_all_selections(list, i + 1, n, k, result);
-                list.pop();
-            }
+        match remaining.len() {
+            0 => natural_number(0),
+            1 => remaining.remove(0),
+            _ => sum(remaining),
         }
     }
 
@@ -466,7 +534,10 @@ impl MathType {
     /*
     a + a => 2 * a
     a + a + -a => a
+    1/2 * a + 1/3 * a => 5/6 * a
 
+    a * a * a^-1 => a^1
+    x^a * x^b => x^(a + b)
      */
     pub fn combine(&self) -> MathType {
         match self.type_name {
@@ -477,76 +548,76 @@ impl MathType {
                     para[i].sort();
                 }
 
+                let terms = para.iter().map(|x| x.split_coefficient()).collect::<Vec<(Rational, MathType)>>();
+
                 let mut new_para = vec![];
-                let mut collected = vec![false; para.len()];
-                for i in 0..para.len() {
-                    let mut count = 1;
+                let mut collected = vec![false; terms.len()];
+                for i in 0..terms.len() {
+                    let mut coefficient = terms[i].0;
 
                     if collected[i] {
                         continue;
                     }
 
-                    for j in i + 1..para.len() {
-                        if para[i] == para[j]{
-                            count += 1;
-                            collected[j] = true;
-                        }
-                        else {
-                            if para[i] == flip_sign(para[j].clone()) {
-                                count -= 1;
+                    for j in i + 1..terms.len() {
+                        if !collected[j] && terms[i].1 == terms[j].1 {
+                            if let Some(next) = coefficient.checked_add(terms[j].0) {
+                                coefficient = next;
                                 collected[j] = true;
                             }
                         }
                     }
 
-                    if count == 1 {
-                        new_para.push(para[i].clone());
-                    } else {
-                        new_para.push(product(vec![natural_number(count), para[i].clone()]));
+                    if coefficient.is_zero() {
+                        continue;
                     }
+
+                    new_para.push(with_coefficient(coefficient, terms[i].1.clone()));
                 }
 
                 return MathType::new(self.type_name.clone(), new_para);
             },
 
-            // MathTypeName::Product => {
-            //     let mut para = self.parameter.iter().map(|x| x.combine()).collect::<Vec<MathType>>();
-
-            //     for i in 0..para.len() {
-            //         para[i].sort();
-            //     }
-
-            //     let mut new_para = vec![];
-            //     let mut collected = vec![false; para.len()];
-            //     for i in 0..para.len() {
-            //         let mut count = 1;
-
-            //         if collected[i] {
-            //             continue;
-            //         }
-
-            //         for j in i + 1..para.len() {
-            //             if para[i] == para[j]{
-            //                 count += 1;
-            //                 collected[j] = true;
-            //             }
-            //             else {
-            //                 if para[i] == reciprocal(para[j].clone()) {
-            //                     count -= 1;
-            //                     collected[j] = true;
-            //                 }
-            //             }
-            //         }
-
-            //         if count == 1 {
-            //             new_para.push(para[i].clone());
-            //         } else {
-            //             new_para.push(power( para[i].clone(), natural_number(count)));
-            //         }
-            //     }
-
-            //     return MathType::new(self.type_name.clone(), new_para);
-            // }
+            MathTypeName::Product => {
+                let mut para = self.parameter.iter().map(|x| x.combine()).collect::<Vec<MathType>>();
+
+                for para in para.iter_mut() {
+                    para.sort();
+                }
+
+                let factors = para.iter().map(|x| x.split_exponent()).collect::<Vec<(MathType, MathType)>>();
+
+                let mut new_para = vec![];
+                let mut collected = vec![false; factors.len()];
+                for i in 0..factors.len() {
+                    if collected[i] {
+                        continue;
+                    }
+
+                    //Constant factors are folded by reduce_neutral
+                    if para[i].constant_value().is_some() {
+                        new_para.push(para[i].clone());
+                        continue;
+                    }
+
+                    let mut exponents = vec![factors[i].1.clone()];
+                    for j in i + 1..factors.len() {
+                        if !collected[j] && para[j].constant_value().is_none() && factors[i].0 == factors[j].0 {
+                            exponents.push(factors[j].1.clone());
+                            collected[j] = true;
+                        }
+                    }
+
+                    if exponents.len() == 1 {
+                        new_para.push(para[i].clone());
+                        continue;
+                    }
+
+                    new_para.push(power(factors[i].0.clone(), sum(exponents).reduce_neutral()));
+                }
+
+                MathType::new(self.type_name.clone(), new_para)
+            },
 
             _ => MathType::new(self.type_name.clone(), self.parameter.iter().map(|x| x.combine()).collect()),
         }
@@ -561,6 +632,10 @@ impl MathType {
     0 * a => 0
     1 * a => a
     2 * a * 3 => 6 * a
+    3 - 5 => -2
+    2^-1 => 1/2
+    a^0 => 1
+    a^1 => a
     product[] => 1
     product[a] => a
      */
@@ -570,23 +645,20 @@ impl MathType {
             MathTypeName::Sum => {
                 let mut sum = vec![];
 
-                let mut const_sum = 0;
+                let mut const_sum = Rational::ZERO;
                 for i in 0..self.parameter.len() {
                     let reduced = self.parameter[i].reduce_neutral();
-                    if matches!(reduced.type_name, MathTypeName::NaturalNumber(_)) {
-                        match reduced.type_name {
-                            MathTypeName::NaturalNumber(value) => const_sum += value,
-                            _ => unreachable!(),
-                        }
-
+                    //Constants that would overflow the folded value stay symbolic
+                    if let Some(next) = reduced.constant_value().and_then(|value| const_sum.checked_add(value)) {
+                        const_sum = next;
                         continue;
                     }
 
                     sum.push(reduced);
                 }
 
-                if const_sum != 0 {
-                    sum.insert(0, natural_number(const_sum));
+                if !const_sum.is_zero() {
+                    sum.insert(0, rational(const_sum));
                 }
 
                 if sum.len() == 0 {
@@ -601,27 +673,23 @@ impl MathType {
             },
             MathTypeName::Product => {
                 let mut prod = vec![];
-                let mut const_prod = 1;
+                let mut const_prod = Rational::ONE;
                 for i in 0..self.parameter.len() {
                     let reduced = self.parameter[i].reduce_neutral();
-                    if matches!(reduced.type_name, MathTypeName::NaturalNumber(_)) {
-                        match reduced.type_name {
-                            MathTypeName::NaturalNumber(value) => const_prod *= value,
-                            _ => unreachable!(),
-                        }
-
+                    if let Some(next) = reduced.constant_value().and_then(|value| const_prod.checked_mul(value)) {
+                        const_prod = next;
                         continue;
                     }
 
                     prod.push(reduced);
                 }
 
-                if const_prod == 0 {
+                if const_prod.is_zero() {
                     return natural_number(0);
                 }
 
-                if const_prod != 1 {
-                    prod.insert(0, natural_number(const_prod));
+                if !const_prod.is_one() {
+                    prod.insert(0, rational(const_prod));
                 }
 
                 if prod.len() == 0 {
@@ -637,8 +705,8 @@ impl MathType {
 
             MathTypeName::FlipSign => {
                 let reduced = self.parameter[0].reduce_neutral();
-                if reduced.type_name == MathTypeName::NaturalNumber(0) {
-                    return natural_number(0);
+                if let Some(value) = reduced.constant_value().and_then(|value| value.checked_neg()) {
+                    return rational(value);
                 }
 
                 if reduced.type_name == MathTypeName::FlipSign {
@@ -647,13 +715,156 @@ impl MathType {
 
                 return MathType::new(MathTypeName::FlipSign, vec![reduced]);
             }
+
+            MathTypeName::Power => {
+                let base = self.parameter[0].reduce_neutral();
+                let exponent = self.parameter[1].reduce_neutral();
+
+                if let (Some(base_value), Some(exponent_value)) = (base.constant_value(), exponent.constant_value()) {
+                    if exponent_value.is_integer() {
+                        if let Ok(exponent_value) = i32::try_from(exponent_value.numerator()) {
+                            //0^-1 and overflowing powers stay symbolic
+                            if let Ok(value) = base_value.pow(exponent_value) {
+                                return rational(value);
+                            }
+                        }
+                    }
+                }
+
+                if let Some(exponent_value) = exponent.constant_value() {
+                    if exponent_value.is_zero() {
+                        return natural_number(1);
+                    }
+
+                    if exponent_value.is_one() {
+                        return base;
+                    }
+                }
+
+                return power(base, exponent);
+            }
             _ => MathType::new(self.type_name.clone(), self.parameter.iter().map(|x| x.reduce_neutral()).collect()),
         }
     }
 
     pub fn get_derivative(&self, delta_var: &MathType) -> MathType {
-        assert!(delta_var.is_variable(), "Derivative can only be calculated for variables");
+        self.try_get_derivative(delta_var).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_get_derivative(&self, delta_var: &MathType) -> Result<MathType, MathError> {
+        if !delta_var.is_variable() {
+            return Err(MathError::NonVariableDerivative(delta_var.get_string()));
+        }
+
+        self.validate()?;
+
+        Ok(self.derivative(delta_var))
+    }
+
+    pub fn get_derivative_wrt(&self, target: &MathType) -> MathType {
+        self.try_get_derivative_wrt(target).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /*
+    Derivative with respect to a subexpression, which is treated as an independent symbol.
+    Occurrences of target are replaced by a fresh variable, the result is expressed in target again.
+    Variables inside target are not connected to it:
+
+    f(a b) c wrt a b => f'(a b) c
+    a b + a wrt a b => 1
+     */
+    pub fn try_get_derivative_wrt(&self, target: &MathType) -> Result<MathType, MathError> {
+        if target.is_variable() {
+            return self.try_get_derivative(target);
+        }
 
+        self.validate()?;
+        target.validate()?;
+
+        let mut index = 0;
+        let symbol = loop {
+            let candidate = variable(&format!("#{}", index));
+            if !self.depends_on(&candidate) && !target.depends_on(&candidate) {
+                break candidate;
+            }
+
+            index += 1;
+        };
+
+        let derivative = self.replace(target, &symbol).derivative(&symbol);
+
+        Ok(derivative.replace(&symbol, target))
+    }
+
+    pub fn derivative_n(&self, orders: &[(MathType, u32)]) -> MathType {
+        self.try_derivative_n(orders).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /*
+    Mixed partial derivative, every variable is derived as often as its order, from left to right:
+
+    f wrt [(x, 2), (y, 1)] => f_xxy
+
+    All steps share one memo table, equal subtrees are only derived once per variable.
+    Subtrees are interned into an ExprPool, so the table is keyed by ids instead of whole trees.
+     */
+    pub fn try_derivative_n(&self, orders: &[(MathType, u32)]) -> Result<MathType, MathError> {
+        if let Some((var, _)) = orders.iter().find(|(var, _)| !var.is_variable()) {
+            return Err(MathError::NonVariableDerivative(var.get_string()));
+        }
+
+        self.validate()?;
+
+        let mut memo = DerivativeMemo {
+            pool: ExprPool::new(),
+            ids: HashMap::new(),
+            results: HashMap::new(),
+        };
+
+        let mut result = self.clone();
+        for (var, order) in orders {
+            let var_id = memo.pool.insert(var);
+
+            for _ in 0..*order {
+                //The previous tree is gone, its addresses may be reused
+                memo.ids.clear();
+                memo.pool.insert_addresses(&result, &mut memo.ids);
+
+                result = result.derivative_memo(var, var_id, &mut memo);
+            }
+        }
+
+        Ok(result)
+    }
+
+    //A function with the name of the variable counts as the variable, like in derivative
+    fn depends_on(&self, delta_var: &MathType) -> bool {
+        self.type_name == delta_var.type_name || self.parameter.iter().any(|x| x.depends_on(delta_var))
+    }
+
+    fn derivative(&self, delta_var: &MathType) -> MathType {
+        self.derivative_with(delta_var, &mut |x| x.derivative(delta_var))
+    }
+
+    //Looks up or computes the derivative of every subtree only once
+    fn derivative_memo(&self, delta_var: &MathType, var_id: ExprId, memo: &mut DerivativeMemo) -> MathType {
+        let id = match memo.ids.get(&(self as *const MathType)) {
+            Some(id) => *id,
+            None => memo.pool.insert(self),
+        };
+
+        if let Some(result) = memo.results.get(&(id, var_id)) {
+            return result.clone();
+        }
+
+        let result = self.derivative_with(delta_var, &mut |x| x.derivative_memo(delta_var, var_id, memo));
+        memo.results.insert((id, var_id), result.clone());
+
+        result
+    }
+
+    //One step of the chain rule, d derives the parameters
+    fn derivative_with(&self, delta_var: &MathType, d: &mut dyn FnMut(&MathType) -> MathType) -> MathType {
         return match self.type_name {
             MathTypeName::Function(ref name) => if self.type_name == delta_var.type_name {
                 MathType::new(MathTypeName::NaturalNumber(1), vec![])
@@ -663,22 +874,22 @@ impl MathType {
                     parameter[i] = delta_var.clone();
     
                     return product(vec![
-                        self.parameter[i].get_derivative(delta_var),
+                        d(&self.parameter[i]),
                         function(&format!("{}'", name), self.parameter.clone())
                     ]);
                 }).collect())
             },
 
-            MathTypeName::NaturalNumber(_) => MathType::new(MathTypeName::NaturalNumber(0), vec![]),
-            MathTypeName::Sum =>  MathType::new(MathTypeName::Sum, self.parameter.iter().map(|x| x.get_derivative(delta_var)).collect()),
-            MathTypeName::FlipSign => MathType::new(MathTypeName::FlipSign, vec![self.parameter[0].get_derivative(delta_var)]),
+            MathTypeName::NaturalNumber(_) | MathTypeName::Rational(_) => MathType::new(MathTypeName::NaturalNumber(0), vec![]),
+            MathTypeName::Sum =>  MathType::new(MathTypeName::Sum, self.parameter.iter().map(&mut *d).collect()),
+            MathTypeName::FlipSign => MathType::new(MathTypeName::FlipSign, vec![d(&self.parameter[0])]),
             MathTypeName::Product => {
                 let mut sum = vec![];
                 for i in 0..self.parameter.len() {
                     let mut prod = vec![];
                     for j in 0..self.parameter.len() {
                         if i == j {
-                            prod.push(self.parameter[j].get_derivative(delta_var));
+                            prod.push(d(&self.parameter[j]));
                         } else {
                             prod.push(self.parameter[j].clone());
                         }
@@ -690,12 +901,12 @@ impl MathType {
             MathTypeName::Exp => {
                 return product(vec![
                     self.clone(), 
-                    self.parameter[0].get_derivative(delta_var),
+                    d(&self.parameter[0]),
                 ]);
             }
             MathTypeName::LogN => {
                 return product(vec![
-                    self.parameter[0].get_derivative(delta_var),
+                    d(&self.parameter[0]),
                     reciprocal(self.parameter[0].clone()), 
                 ]);
             }
@@ -703,7 +914,32 @@ impl MathType {
                 let base = self.parameter[0].clone();
                 let exponent = self.parameter[1].clone();
 
-                return exponential(product(vec![exponent, logarithm(base)])).get_derivative(delta_var);
+                //b^n => n * b^(n - 1) * b'
+                if !exponent.depends_on(delta_var) {
+                    return product(vec![
+                        exponent.clone(),
+                        power(base.clone(), sum(vec![exponent, integer(-1)])),
+                        d(&self.parameter[0]),
+                    ]);
+                }
+
+                //a^e => a^e * ln(a) * e'
+                if !base.depends_on(delta_var) {
+                    return product(vec![
+                        self.clone(),
+                        logarithm(base),
+                        d(&self.parameter[1]),
+                    ]);
+                }
+
+                //b^e => b^e * (e' * ln(b) + e * b' / b)
+                return product(vec![
+                    self.clone(),
+                    sum(vec![
+                        product(vec![d(&self.parameter[1]), logarithm(base.clone())]),
+                        product(vec![exponent, d(&self.parameter[0]), reciprocal(base)]),
+                    ]),
+                ]);
             }
             MathTypeName::Undefined => MathType::new(MathTypeName::Undefined, vec![]) 
         };
@@ -713,6 +949,13 @@ impl MathType {
         match self.type_name {
             MathTypeName::Undefined => "Undefined".to_string(),
             MathTypeName::NaturalNumber(value) => value.to_string(),
+            MathTypeName::Rational(value) => {
+                if value.is_integer() {
+                    return format!("rational({})", value.numerator());
+                }
+
+                return format!("rational({}, {})", value.numerator(), value.denominator());
+            },
             MathTypeName::Sum => {
                 return format!("+({})", self.parameter.iter().map(|x| {
                     if x.type_name.precedence() <= self.type_name.precedence() {
@@ -762,9 +1005,17 @@ impl MathType {
         match self.type_name {
             MathTypeName::Undefined => "Undefined".to_string(),
             MathTypeName::NaturalNumber(value) => value.to_string(),
+            MathTypeName::Rational(value) => {
+                if value.is_integer() {
+                    return value.numerator().to_string();
+                }
+
+                let sign = if value.is_negative() { "-" } else { "" };
+                return format!("{}frac({}, {})", sign, value.numerator().abs(), value.denominator());
+            },
             MathTypeName::Sum => {
                 if self.parameter.len() == 0 {
-                    return "\"zero\"".to_string();
+                    return format!("\"{}\"", typst_symbols::EMPTY_SUM);
                 }
 
                 if self.parameter.len() == 1 {
@@ -780,31 +1031,47 @@ impl MathType {
                 
                 }).collect::<Vec<String>>().join(" + ");
             },
-            MathTypeName::FlipSign => "-".to_owned() + &self.parameter[0].get_typst_string(),
+            MathTypeName::FlipSign => {
+                //-a b is read as -(a b), but -a + b only negates a
+                if self.parameter[0].type_name.precedence() <= MathTypeName::Sum.precedence() {
+                    return format!("-({})", self.parameter[0].get_typst_string());
+                }
+
+                "-".to_owned() + &self.parameter[0].get_typst_string()
+            },
             
             MathTypeName::Product => {
                 if self.parameter.len() == 0 {
-                    return "\"one\"".to_string();
+                    return format!("\"{}\"", typst_symbols::EMPTY_PRODUCT);
+                }
+
+                //Factors with negative exponents go below the fraction bar
+                if self.parameter.iter().any(|x| x.inverted_power().is_some()) {
+                    let numerator = self.parameter.iter().filter(|x| x.inverted_power().is_none()).cloned().collect();
+                    let denominator = self.parameter.iter().filter_map(|x| x.inverted_power()).collect();
+
+                    return typst_fraction(numerator, denominator);
                 }
 
                 let mut string = "".to_string();
 
                 for i in 0..self.parameter.len() {
                     let para = &self.parameter[i];
-                    if para.type_name.precedence() <= self.type_name.precedence() {
-                        string += &format!("({})", para.get_typst_string());
+                    let para_string = para.get_typst_string();
+
+                    //a -b would be read as a subtraction
+                    if para.type_name.precedence() <= self.type_name.precedence() || (i > 0 && para_string.starts_with('-')) {
+                        string += &format!("({})", para_string);
                     } else {
-                        string += &para.get_typst_string();
+                        string += &para_string;
                     }
                     
                     
                     if i < self.parameter.len() - 1 {
-                        let pair = (self.parameter[i].type_name.clone(), self.parameter[i + 1].type_name.clone());
-                        // println!("{:?}", pair);
-    
-                        string += match pair {
-                            (MathTypeName::NaturalNumber(_), MathTypeName::NaturalNumber(_)) => " dot ",
-                            _ => " ",
+                        string += if self.parameter[i].type_name.is_number() && self.parameter[i + 1].type_name.is_number() {
+                            " dot "
+                        } else {
+                            " "
                         };
                     }
                 }
@@ -829,6 +1096,10 @@ impl MathType {
             }
 
             MathTypeName::Power => {
+                if let Some(denominator) = self.inverted_power() {
+                    return typst_fraction(vec![], vec![denominator]);
+                }
+
                 return format!("({})^({})", self.parameter[0].get_typst_string(), self.parameter[1].get_typst_string());
             }   
         }
@@ -836,18 +1107,227 @@ impl MathType {
 }
 
 
-fn iterate_set_bits(mut value: u64) -> impl Iterator<Item=u32> {
-    //return (0..64).filter(move |x| value >> x & 1 != 0);
+fn typst_fraction(numerator: Vec<MathType>, denominator: Vec<MathType>) -> String {
+    let part = |mut factors: Vec<MathType>| match factors.len() {
+        0 => "1".to_string(),
+        1 => factors.remove(0).get_typst_string(),
+        _ => product(factors).get_typst_string(),
+    };
 
-    return std::iter::from_fn(move || {
-        if value != 0 {
-            let index = value.trailing_zeros();
-            value ^= 1_u64 << index;
-            
-            Some(index)
+    format!("{}({}, {})", typst_symbols::FRACTION, part(numerator), part(denominator))
+}
+
+fn summand_factors(summand: &MathType) -> Vec<MathType> {
+    if summand.type_name == MathTypeName::Product {
+        return summand.parameter.clone();
+    }
+
+    vec![summand.clone()]
+}
+
+//Adds up the constant summands into one last summand, constants that would overflow stay as they are
+fn fold_constant_summands(summands: Vec<MathType>) -> Vec<MathType> {
+    let mut result = vec![];
+    let mut constant = Rational::ZERO;
+
+    for summand in summands {
+        match summand.reduce_neutral().constant_value().and_then(|value| constant.checked_add(value)) {
+            Some(next) => constant = next,
+            None => result.push(summand),
+        }
+    }
+
+    if !constant.is_zero() {
+        result.push(rational(constant));
+    }
+
+    result
+}
+
+//Non constant factor contained in the most summands, at least two, ties go to the first one seen
+fn most_frequent_factor(summands: &[MathType]) -> Option<MathType> {
+    let mut counts: Vec<(MathType, usize)> = vec![];
+    let mut index: HashMap<MathType, usize> = HashMap::new();
+
+    for summand in summands {
+        let mut seen = HashSet::new();
+
+        for factor in summand_factors(summand) {
+            if factor.constant_value().is_some() || !seen.insert(factor.clone()) {
+                continue;
+            }
+
+            match index.get(&factor) {
+                Some(i) => counts[*i].1 += 1,
+                None => {
+                    index.insert(factor.clone(), counts.len());
+                    counts.push((factor, 1));
+                },
+            }
         }
-        else {
-            None
+    }
+
+    let mut best: Option<(MathType, usize)> = None;
+    for (factor, count) in counts {
+        if count >= 2 && best.as_ref().is_none_or(|x| count > x.1) {
+            best = Some((factor, count));
         }
-    });
-}
\ No newline at end of file
+    }
+
+    best.map(|x| x.0)
+}
+
+//Inverse of split_coefficient
+pub(crate) fn with_coefficient(coefficient: Rational, rest: MathType) -> MathType {
+    if rest.type_name == MathTypeName::Product && rest.parameter.is_empty() {
+        return rational(coefficient);
+    }
+
+    if coefficient.is_one() {
+        return rest;
+    }
+
+    if coefficient == -Rational::ONE {
+        return flip_sign(rest);
+    }
+
+    if rest.type_name == MathTypeName::Product {
+        let mut para = vec![rational(coefficient)];
+        para.extend(rest.parameter);
+        return product(para);
+    }
+
+    product(vec![rational(coefficient), rest])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflowing_product_stays_symbolic() {
+        let tree = product(vec![natural_number(u128::MAX / 2), natural_number(4), variable("x")]);
+
+        assert_eq!(tree.simplify().get_string(), "*(4, 170141183460469231731687303715884105727, x)");
+    }
+
+    #[test]
+    fn overflowing_sum_stays_symbolic() {
+        let tree = sum(vec![natural_number(u128::MAX / 2), natural_number(u128::MAX / 2), variable("x")]);
+
+        assert_eq!(tree.simplify().get_string(), "+(170141183460469231731687303715884105727, 170141183460469231731687303715884105727, x)");
+    }
+
+    #[test]
+    fn folds_constants_below_the_limit() {
+        let tree = product(vec![natural_number(u128::MAX / 4), natural_number(2), variable("x")]);
+
+        assert_eq!(tree.simplify().get_string(), "*(170141183460469231731687303715884105726, x)");
+    }
+
+    #[test]
+    fn derivative_n_matches_repeated_derivatives() {
+        let (x, y) = (variable("x"), variable("y"));
+        let tree = MathType::parse("pow(x, y) * ln(x) + exp(x * y) / x + f(x * y, x)").unwrap();
+
+        let expected = tree.get_derivative(&x).get_derivative(&x).get_derivative(&y);
+        assert!(tree.derivative_n(&[(x.clone(), 2), (y.clone(), 1)]) == expected);
+    }
+
+    #[test]
+    fn derivative_wrt_a_sum_inside_a_function() {
+        let target = MathType::parse("a + b").unwrap();
+        let tree = MathType::parse("f(a + b) * c + a").unwrap();
+
+        let expected = product(vec![function("f'", vec![target.clone()]), variable("c")]);
+        assert!(tree.get_derivative_wrt(&target).simplify() == expected.simplify());
+    }
+
+    #[test]
+    fn derivative_wrt_a_repeated_product() {
+        let target = MathType::parse("a * b").unwrap();
+        let tree = MathType::parse("a * b + exp(a * b) + (b * a)^2 + a").unwrap();
+
+        let expected = MathType::parse("1 + exp(a * b) + 2 * a * b").unwrap();
+        assert!(tree.get_derivative_wrt(&target).simplify() == expected.simplify());
+    }
+
+    #[test]
+    fn derivative_wrt_a_variable_is_get_derivative() {
+        let x = variable("x");
+        let tree = MathType::parse("x^2 * f(x)").unwrap();
+
+        assert!(tree.get_derivative_wrt(&x) == tree.get_derivative(&x));
+    }
+
+    fn at(name: &str, value: i128) -> HashMap<String, Rational> {
+        HashMap::from([(name.to_string(), Rational::integer(value))])
+    }
+
+    #[test]
+    fn partial_eval_removes_neutral_elements() {
+        let tree = MathType::parse("x + y").unwrap();
+        assert_eq!(tree.partial_eval(&at("x", 0)).get_string(), "y");
+
+        let tree = MathType::parse("f(x) * 1").unwrap();
+        assert!(tree.partial_eval(&at("x", 3)) == function("f", vec![natural_number(3)]));
+
+        let tree = MathType::parse("f(x) * exp(x - 3)").unwrap();
+        assert!(tree.partial_eval(&at("x", 3)) == function("f", vec![natural_number(3)]));
+    }
+
+    #[test]
+    fn partial_eval_folds_known_values() {
+        let tree = MathType::parse("x^2 + y").unwrap();
+        assert!(tree.partial_eval(&at("x", 3)) == sum(vec![natural_number(9), variable("y")]));
+
+        let tree = MathType::parse("exp(0 * y) * x").unwrap();
+        assert!(tree.partial_eval(&at("x", 2)) == natural_number(2));
+
+        let tree = MathType::parse("x * y - 2 * z").unwrap();
+        let values = HashMap::from([
+            ("x".to_string(), Rational::integer(2)),
+            ("y".to_string(), Rational::integer(5)),
+            ("z".to_string(), Rational::integer(5)),
+        ]);
+        assert!(tree.partial_eval(&values) == natural_number(0));
+    }
+
+    #[test]
+    fn partial_eval_reports_division_by_zero() {
+        let tree = MathType::parse("x / (x - 3)").unwrap();
+        assert_eq!(tree.try_partial_eval(&at("x", 3)).err(), Some(MathError::DivisionByZero));
+        assert!(tree.try_partial_eval(&at("x", 4)).unwrap() == natural_number(4));
+
+        //The zero only appears after reduce_neutral
+        let tree = MathType::parse("1 / (0 * y)").unwrap();
+        assert_eq!(tree.try_partial_eval(&at("x", 1)).err(), Some(MathError::DivisionByZero));
+    }
+
+    fn factors_to(input: &str, expected: &str) -> bool {
+        MathType::parse(input).unwrap().factor_out() == MathType::parse(expected).unwrap()
+    }
+
+    #[test]
+    fn factor_out_folds_the_coefficient() {
+        assert!(factors_to("a + a", "2 * a"));
+        assert!(factors_to("2 * a + 3 * a + b", "b + 5 * a"));
+        assert!(factors_to("a * 3 + a * (-3) + b", "b"));
+        assert!(factors_to("a * x + a * y + a * 1 + a * 2", "a * (x + y + 3)"));
+    }
+
+    #[test]
+    fn factor_out_of_long_sums() {
+        assert!(factors_to("a * b + a * c + a * d + a * e + f", "f + a * (b + c + d + e)"));
+        assert!(factors_to("a * b + c * d + a * e + c * g + h", "h + a * (b + e) + c * (d + g)"));
+        assert!(factors_to("a + b + c + d", "a + b + c + d"));
+    }
+
+    #[test]
+    fn factor_out_is_recursive() {
+        assert!(factors_to("x * y * z + x * y * w + x * v", "x * (v + y * (z + w))"));
+        assert!(factors_to("a * b + a * b * c + a", "a * (1 + b * (1 + c))"));
+        assert!(factors_to("f(a * b + a * c) + exp(x + x)", "f(a * (b + c)) + exp(2 * x)"));
+    }
+}
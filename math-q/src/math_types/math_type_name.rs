@@ -1,10 +1,14 @@
 use std::cmp::Ordering;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd)]
+use super::rational::Rational;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum MathTypeName {
     Undefined,
     Function(String),
     NaturalNumber(u128),
+    //Constants that are not natural numbers, see math_type::rational
+    Rational(Rational),
     Sum,
     FlipSign,
     Product,
@@ -22,11 +26,18 @@ impl Ord for MathTypeName {
         return match (self.clone(), other.clone()) {
             (MathTypeName::Function(a), MathTypeName::Function(b)) => a.cmp(&b),
             (MathTypeName::NaturalNumber(a), MathTypeName::NaturalNumber(b)) => a.cmp(&b),
+            (MathTypeName::Rational(a), MathTypeName::Rational(b)) => a.cmp(&b),
             _ => Ordering::Equal,
         };
     }
 }
 
+impl PartialOrd for MathTypeName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl MathTypeName {
     pub fn parameter_range(&self) -> (usize, usize) {
         match self {
@@ -46,6 +57,13 @@ impl MathTypeName {
     }
 
     pub fn precedence(&self) -> u8 {
+        //-3 and -frac(1, 2) bind like a FlipSign
+        if let MathTypeName::Rational(value) = self {
+            if value.is_negative() {
+                return 3;
+            }
+        }
+
         if self.parameter_range().1 == 0 || matches!(self, MathTypeName::Function(_)) {
             return u8::MAX;
         }
@@ -67,17 +85,22 @@ impl MathTypeName {
         }
     }
 
+    pub fn is_number(&self) -> bool {
+        matches!(self, MathTypeName::NaturalNumber(_) | MathTypeName::Rational(_))
+    }
+
     pub fn index(&self) -> u8 {
         match self {
             MathTypeName::Undefined => 0,
             MathTypeName::NaturalNumber(_) => 1,
-            MathTypeName::Function(_) => 2,
-            MathTypeName::Power => 3,
-            MathTypeName::Sum => 4,
-            MathTypeName::FlipSign => 5,
-            MathTypeName::Product => 6,
-            MathTypeName::Exp => 7,
-            MathTypeName::LogN => 8,
+            MathTypeName::Rational(_) => 2,
+            MathTypeName::Function(_) => 3,
+            MathTypeName::Power => 4,
+            MathTypeName::Sum => 5,
+            MathTypeName::FlipSign => 6,
+            MathTypeName::Product => 7,
+            MathTypeName::Exp => 8,
+            MathTypeName::LogN => 9,
         }
     }
 }
\ No newline at end of file
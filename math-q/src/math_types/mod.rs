@@ -1,3 +1,18 @@
+pub mod canonical;
+pub mod compiled;
+pub mod dag;
+pub mod derivatives;
+pub mod dual;
+pub mod egraph;
+pub mod eval;
+pub mod factor;
+pub mod math_error;
 pub mod math_type;
 pub mod math_type_name;
+pub mod parser;
+pub mod polynomial;
+pub mod rational;
+pub mod rewrite;
+pub mod taylor;
+pub mod typst_parser;
 pub mod typst_symbols;
\ No newline at end of file
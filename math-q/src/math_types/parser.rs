@@ -0,0 +1,429 @@
+use std::fmt;
+
+use super::{math_type::{self, MathType}, math_type_name::MathTypeName, rational::Rational};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(position: usize, message: &str) -> ParseError {
+        ParseError {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u128),
+    Name(String),
+    Symbol(char),
+    End,
+}
+
+/*
+Names are a base identifier followed by primes and attachments:
+f'' => name
+x_0 => name
+w^(1)_(00) => name, a superscript directly followed by a subscript belongs to the name
+?a => name, pattern variable for rewrite rules
+x^2 => power
+x_0^2 => power
+ */
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars = input.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+
+            let text = chars[start..i].iter().collect::<String>();
+            let value = text.parse::<u128>().map_err(|_| ParseError::new(start, "Number literal too large"))?;
+            tokens.push((Token::Number(value), start));
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '?' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_alphanumeric() {
+                i += 1;
+            }
+            while i < chars.len() && chars[i] == '\'' {
+                i += 1;
+            }
+
+            loop {
+                if i < chars.len() && chars[i] == '_' {
+                    i = read_attachment(&chars, i + 1)?;
+                    continue;
+                }
+
+                if i < chars.len() && chars[i] == '^' {
+                    if let Ok(end) = read_attachment(&chars, i + 1) {
+                        if end < chars.len() && chars[end] == '_' {
+                            i = end;
+                            continue;
+                        }
+                    }
+                }
+
+                break;
+            }
+
+            tokens.push((Token::Name(chars[start..i].iter().collect()), start));
+            continue;
+        }
+
+        if "+-*/^(),".contains(c) {
+            tokens.push((Token::Symbol(c), i));
+            i += 1;
+            continue;
+        }
+
+        return Err(ParseError::new(i, &format!("Unexpected character '{}'", c)));
+    }
+
+    tokens.push((Token::End, chars.len()));
+    Ok(tokens)
+}
+
+//Returns the index after a parenthesized group or an alphanumeric run
+pub(crate) fn read_attachment(chars: &[char], start: usize) -> Result<usize, ParseError> {
+    if start < chars.len() && chars[start] == '(' {
+        let mut depth = 0;
+        for (i, c) in chars.iter().enumerate().skip(start) {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(i + 1);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        return Err(ParseError::new(start, "Unclosed parenthesis in attachment"));
+    }
+
+    let mut end = start;
+    while end < chars.len() && chars[end].is_alphanumeric() {
+        end += 1;
+    }
+
+    if end == start {
+        return Err(ParseError::new(start, "Expected attachment"));
+    }
+
+    Ok(end)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+}
+
+/*
+sum := term (('+' | '-') term)*
+term := unary (('*' | '/') unary)*
+unary := '-' unary | power
+power := atom ('^' unary)?
+atom := number | name | name '(' list ')' | '(' sum ')' | '+' '(' list ')' | '*' '(' list ')'
+ */
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.index].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if self.index < self.tokens.len() - 1 {
+            self.index += 1;
+        }
+
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        if *self.peek() != Token::Symbol(symbol) {
+            return Err(ParseError::new(self.position(), &format!("Expected '{}'", symbol)));
+        }
+
+        self.next();
+        Ok(())
+    }
+
+    fn parse_sum(&mut self) -> Result<MathType, ParseError> {
+        let mut summands = vec![self.parse_term()?];
+
+        loop {
+            match self.peek() {
+                Token::Symbol('+') => {
+                    self.next();
+                    summands.push(self.parse_term()?);
+                },
+                Token::Symbol('-') => {
+                    self.next();
+                    summands.push(math_type::flip_sign(self.parse_term()?));
+                },
+                _ => break,
+            }
+        }
+
+        if summands.len() == 1 {
+            return Ok(summands.remove(0));
+        }
+
+        Ok(math_type::sum(summands))
+    }
+
+    fn parse_term(&mut self) -> Result<MathType, ParseError> {
+        let mut factors = vec![self.parse_unary()?];
+
+        loop {
+            match self.peek() {
+                Token::Symbol('*') => {
+                    self.next();
+                    factors.push(self.parse_unary()?);
+                },
+                Token::Symbol('/') => {
+                    self.next();
+                    factors.push(math_type::reciprocal(self.parse_unary()?));
+                },
+                _ => break,
+            }
+        }
+
+        if factors.len() == 1 {
+            return Ok(factors.remove(0));
+        }
+
+        Ok(math_type::product(factors))
+    }
+
+    fn parse_unary(&mut self) -> Result<MathType, ParseError> {
+        if *self.peek() == Token::Symbol('-') {
+            self.next();
+            return Ok(math_type::flip_sign(self.parse_unary()?));
+        }
+
+        self.parse_power()
+    }
+
+    fn parse_power(&mut self) -> Result<MathType, ParseError> {
+        let base = self.parse_atom()?;
+
+        if *self.peek() == Token::Symbol('^') {
+            self.next();
+            let exponent = self.parse_unary()?;
+            return Ok(math_type::power(base, exponent));
+        }
+
+        Ok(base)
+    }
+
+    fn parse_atom(&mut self) -> Result<MathType, ParseError> {
+        let position = self.position();
+
+        match self.next() {
+            Token::Number(value) => Ok(math_type::natural_number(value)),
+            Token::Name(name) => {
+                if *self.peek() == Token::Symbol('(') {
+                    let parameter = self.parse_list()?;
+                    return build_function(&name, parameter, position);
+                }
+
+                if name == "Undefined" {
+                    return Ok(MathType::new(MathTypeName::Undefined, vec![]));
+                }
+
+                Ok(math_type::variable(&name))
+            },
+            Token::Symbol('(') => {
+                let inner = self.parse_sum()?;
+                self.expect(')')?;
+                Ok(inner)
+            },
+            Token::Symbol('+') if *self.peek() == Token::Symbol('(') => Ok(math_type::sum(self.parse_list()?)),
+            Token::Symbol('*') if *self.peek() == Token::Symbol('(') => Ok(math_type::product(self.parse_list()?)),
+            Token::End => Err(ParseError::new(position, "Unexpected end of input")),
+            Token::Symbol(c) => Err(ParseError::new(position, &format!("Unexpected '{}'", c))),
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<MathType>, ParseError> {
+        self.expect('(')?;
+
+        let mut list = vec![];
+        if *self.peek() == Token::Symbol(')') {
+            self.next();
+            return Ok(list);
+        }
+
+        loop {
+            list.push(self.parse_sum()?);
+
+            match self.peek() {
+                Token::Symbol(',') => {
+                    self.next();
+                },
+                Token::Symbol(')') => {
+                    self.next();
+                    return Ok(list);
+                },
+                _ => return Err(ParseError::new(self.position(), "Expected ',' or ')'")),
+            }
+        }
+    }
+}
+
+fn build_function(name: &str, parameter: Vec<MathType>, position: usize) -> Result<MathType, ParseError> {
+    let type_name = match name {
+        "exp" => MathTypeName::Exp,
+        "ln" => MathTypeName::LogN,
+        "pow" => MathTypeName::Power,
+        "rational" => return build_rational(&parameter, position),
+        _ => MathTypeName::Function(name.to_string()),
+    };
+
+    MathType::try_new(type_name, parameter).map_err(|error| ParseError::new(position, &error.to_string()))
+}
+
+//rational(-1, 2) as printed by get_string, both parameters have to be integer literals
+fn build_rational(parameter: &[MathType], position: usize) -> Result<MathType, ParseError> {
+    let mut values = vec![];
+
+    for para in parameter {
+        let value = match para.type_name {
+            MathTypeName::NaturalNumber(value) => i128::try_from(value).ok(),
+            MathTypeName::FlipSign => match para.parameter[0].type_name {
+                MathTypeName::NaturalNumber(value) => i128::try_from(value).ok().map(|x| -x),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        values.push(value.ok_or_else(|| ParseError::new(position, "Expected integer literal in rational"))?);
+    }
+
+    let value = match values[..] {
+        [numerator] => Rational::integer(numerator),
+        [numerator, denominator] => Rational::try_new(numerator, denominator).map_err(|error| ParseError::new(position, &error.to_string()))?,
+        _ => return Err(ParseError::new(position, &format!("'rational' takes 1 or 2 parameters, found {}", parameter.len()))),
+    };
+
+    Ok(math_type::rational(value))
+}
+
+impl MathType {
+    /*
+    a + b - c => +(a, b, -(c))
+    a * b / c => *(a, b, pow(c, -(1)))
+    -x^2 => -(pow(x, 2))
+    f(x, y), exp(x), ln(x), pow(x, y), rational(-1, 2)
+    +(a, b), *(a, b) as printed by get_string
+     */
+    pub fn parse(input: &str) -> Result<MathType, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            index: 0,
+        };
+
+        let result = parser.parse_sum()?;
+
+        if *parser.peek() != Token::End {
+            return Err(ParseError::new(parser.position(), "Unexpected trailing input"));
+        }
+
+        Ok(result)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(input: &str) {
+        let tree = MathType::parse(input).unwrap();
+        let printed = tree.get_string();
+        let reparsed = MathType::parse(&printed).unwrap_or_else(|error| panic!("{} reparsing {}", error, printed));
+
+        assert!(tree == reparsed, "{} => {} => {}", input, printed, reparsed.get_string());
+        assert_eq!(printed, reparsed.get_string());
+    }
+
+    #[test]
+    fn round_trips_through_get_string() {
+        for input in [
+            "a + b - c",
+            "a * b / c",
+            "-x^2",
+            "x^y^z",
+            "(a + b) * (c - d)",
+            "f(x, y) + g'(x) * f''(x)",
+            "exp(x) * ln(x + 1)",
+            "pow(x, 2)",
+            "rational(-1, 2) * x + rational(3)",
+            "w^(1)_(00) * x_0 + x_0^2",
+            "Undefined",
+            "+() + *()",
+            "x ^ 2",
+            "x^-1",
+            "x^ (a + b)",
+        ] {
+            round_trip(input);
+        }
+    }
+
+    #[test]
+    fn power_accepts_whitespace_and_sign() {
+        let x = math_type::variable("x");
+
+        assert!(MathType::parse("x ^ 2").unwrap() == math_type::power(x.clone(), math_type::natural_number(2)));
+        assert!(MathType::parse("x^ 2").unwrap() == math_type::power(x.clone(), math_type::natural_number(2)));
+        assert!(MathType::parse("x^-1").unwrap() == math_type::reciprocal(x));
+    }
+
+    #[test]
+    fn names_keep_attachments() {
+        assert!(MathType::parse("w^(1)_(00)").unwrap() == math_type::variable("w^(1)_(00)"));
+        assert!(MathType::parse("x_0^2").unwrap() == math_type::power(math_type::variable("x_0"), math_type::natural_number(2)));
+    }
+
+    #[test]
+    fn reports_position() {
+        assert_eq!(MathType::parse("a + $").err().unwrap().position, 4);
+        assert!(MathType::parse("f(a, b").is_err());
+    }
+}
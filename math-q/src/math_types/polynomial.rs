@@ -0,0 +1,801 @@
+use std::{cmp::Ordering, collections::BTreeMap, ops::{Add, Mul, Neg, Sub}};
+
+use super::{math_error::MathError, math_type::{self, MathType}, math_type_name::MathTypeName, rational::{gcd, Rational}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonomialOrder {
+    Lex,
+    GrLex,
+    GrevLex,
+}
+
+impl MonomialOrder {
+    pub fn compare(&self, a: &[u32], b: &[u32]) -> Ordering {
+        match self {
+            MonomialOrder::Lex => a.cmp(b),
+            MonomialOrder::GrLex => total_degree(a).cmp(&total_degree(b)).then_with(|| a.cmp(b)),
+            //Ties are broken by the last variable, the smaller exponent there is the bigger monomial
+            MonomialOrder::GrevLex => total_degree(a).cmp(&total_degree(b)).then_with(|| {
+                for i in (0..a.len()).rev() {
+                    if a[i] != b[i] {
+                        return b[i].cmp(&a[i]);
+                    }
+                }
+
+                Ordering::Equal
+            }),
+        }
+    }
+}
+
+fn total_degree(exponents: &[u32]) -> u64 {
+    exponents.iter().map(|x| *x as u64).sum()
+}
+
+/*
+Sparse polynomial with rational coefficients.
+exponents[i] belongs to variables[i], a variable can be any subtree that is treated as opaque.
+Zero coefficients are never stored.
+ */
+#[derive(Clone, PartialEq, Eq)]
+pub struct Polynomial {
+    variables: Vec<MathType>,
+    terms: BTreeMap<Vec<u32>, Rational>,
+}
+
+impl Polynomial {
+    pub fn zero(variables: &[MathType]) -> Polynomial {
+        Polynomial {
+            variables: variables.to_vec(),
+            terms: BTreeMap::new(),
+        }
+    }
+
+    pub fn constant(variables: &[MathType], value: Rational) -> Polynomial {
+        let mut result = Polynomial::zero(variables);
+        result.add_term(vec![0; variables.len()], value).unwrap();
+        result
+    }
+
+    pub fn variable(variables: &[MathType], index: usize) -> Polynomial {
+        let mut exponents = vec![0; variables.len()];
+        exponents[index] = 1;
+
+        let mut result = Polynomial::zero(variables);
+        result.add_term(exponents, Rational::ONE).unwrap();
+        result
+    }
+
+    pub fn monomial(variables: &[MathType], exponents: Vec<u32>, coefficient: Rational) -> Polynomial {
+        let mut result = Polynomial::zero(variables);
+        result.add_term(exponents, coefficient).unwrap();
+        result
+    }
+
+    pub fn variables(&self) -> &[MathType] {
+        &self.variables
+    }
+
+    pub fn terms(&self) -> impl Iterator<Item = (&Vec<u32>, &Rational)> {
+        self.terms.iter()
+    }
+
+    pub fn term_count(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn constant_value(&self) -> Option<Rational> {
+        match self.terms.len() {
+            0 => Some(Rational::ZERO),
+            1 => self.terms.get(&vec![0; self.variables.len()]).copied(),
+            _ => None,
+        }
+    }
+
+    pub fn total_degree(&self) -> Option<u64> {
+        self.terms.keys().map(|x| total_degree(x)).max()
+    }
+
+    pub fn degree(&self, index: usize) -> Option<u32> {
+        self.terms.keys().map(|x| x[index]).max()
+    }
+
+    //Terms from the biggest to the smallest monomial
+    pub fn sorted_terms(&self, order: MonomialOrder) -> Vec<(&Vec<u32>, Rational)> {
+        let mut result = self.terms.iter().map(|(x, c)| (x, *c)).collect::<Vec<(&Vec<u32>, Rational)>>();
+        result.sort_by(|a, b| order.compare(b.0, a.0));
+        result
+    }
+
+    pub fn leading_term(&self, order: MonomialOrder) -> Option<(&Vec<u32>, Rational)> {
+        self.terms.iter().max_by(|a, b| order.compare(a.0, b.0)).map(|(x, c)| (x, *c))
+    }
+
+    //Coefficient of variables[index]^degree as a polynomial in the same variables
+    pub fn coefficient(&self, index: usize, degree: u32) -> Polynomial {
+        let mut result = Polynomial::zero(&self.variables);
+
+        for (exponents, coefficient) in &self.terms {
+            if exponents[index] == degree {
+                let mut exponents = exponents.clone();
+                exponents[index] = 0;
+                result.terms.insert(exponents, *coefficient);
+            }
+        }
+
+        result
+    }
+
+    pub fn derivative(&self, index: usize) -> Result<Polynomial, MathError> {
+        let mut result = Polynomial::zero(&self.variables);
+
+        for (exponents, coefficient) in &self.terms {
+            if exponents[index] == 0 {
+                continue;
+            }
+
+            let mut exponents = exponents.clone();
+            let factor = Rational::integer(exponents[index] as i128);
+            exponents[index] -= 1;
+
+            result.add_term(exponents, coefficient.checked_mul(factor).ok_or(MathError::Overflow)?)?;
+        }
+
+        Ok(result)
+    }
+
+    fn add_term(&mut self, exponents: Vec<u32>, coefficient: Rational) -> Result<(), MathError> {
+        let sum = match self.terms.get(&exponents) {
+            Some(value) => value.checked_add(coefficient).ok_or(MathError::Overflow)?,
+            None => coefficient,
+        };
+
+        if sum.is_zero() {
+            self.terms.remove(&exponents);
+        } else {
+            self.terms.insert(exponents, sum);
+        }
+
+        Ok(())
+    }
+
+    //Same polynomial over variables, which has to contain all current variables
+    pub fn with_variables(&self, variables: &[MathType]) -> Polynomial {
+        let indices = self.variables.iter().map(|x| variables.iter().position(|y| y == x).expect("Missing variable")).collect::<Vec<usize>>();
+
+        let mut result = Polynomial::zero(variables);
+        for (exponents, coefficient) in &self.terms {
+            let mut new_exponents = vec![0; variables.len()];
+            for (i, exponent) in exponents.iter().enumerate() {
+                new_exponents[indices[i]] = *exponent;
+            }
+
+            result.terms.insert(new_exponents, *coefficient);
+        }
+
+        result
+    }
+
+    //Both polynomials over the union of their variables
+    pub fn unify(a: &Polynomial, b: &Polynomial) -> (Polynomial, Polynomial) {
+        if a.variables == b.variables {
+            return (a.clone(), b.clone());
+        }
+
+        let mut variables = a.variables.clone();
+        for variable in &b.variables {
+            if !variables.contains(variable) {
+                variables.push(variable.clone());
+            }
+        }
+
+        (a.with_variables(&variables), b.with_variables(&variables))
+    }
+
+    pub fn try_add(&self, other: &Polynomial) -> Result<Polynomial, MathError> {
+        let (mut result, other) = Polynomial::unify(self, other);
+        for (exponents, coefficient) in other.terms {
+            result.add_term(exponents, coefficient)?;
+        }
+
+        Ok(result)
+    }
+
+    pub fn try_neg(&self) -> Result<Polynomial, MathError> {
+        self.try_scale(-Rational::ONE)
+    }
+
+    pub fn try_sub(&self, other: &Polynomial) -> Result<Polynomial, MathError> {
+        self.try_add(&other.try_neg()?)
+    }
+
+    pub fn try_scale(&self, factor: Rational) -> Result<Polynomial, MathError> {
+        let mut result = Polynomial::zero(&self.variables);
+        if factor.is_zero() {
+            return Ok(result);
+        }
+
+        for (exponents, coefficient) in &self.terms {
+            result.terms.insert(exponents.clone(), coefficient.checked_mul(factor).ok_or(MathError::Overflow)?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn try_mul(&self, other: &Polynomial) -> Result<Polynomial, MathError> {
+        let (left, right) = Polynomial::unify(self, other);
+        let mut result = Polynomial::zero(&left.variables);
+
+        for (left_exponents, left_coefficient) in &left.terms {
+            for (right_exponents, right_coefficient) in &right.terms {
+                let mut exponents = left_exponents.clone();
+                for (i, exponent) in right_exponents.iter().enumerate() {
+                    exponents[i] = exponents[i].checked_add(*exponent).ok_or(MathError::Overflow)?;
+                }
+
+                result.add_term(exponents, left_coefficient.checked_mul(*right_coefficient).ok_or(MathError::Overflow)?)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn try_pow(&self, exponent: u32) -> Result<Polynomial, MathError> {
+        let mut result = Polynomial::constant(&self.variables, Rational::ONE);
+        let mut square = self.clone();
+        let mut exponent = exponent;
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.try_mul(&square)?;
+            }
+
+            exponent >>= 1;
+            if exponent > 0 {
+                square = square.try_mul(&square)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /*
+    Multivariate division by repeatedly removing the leading term under order.
+    self = quotient * divisor + remainder, where no term of remainder is divisible by the leading term of divisor
+     */
+    pub fn div_rem(&self, divisor: &Polynomial, order: MonomialOrder) -> Result<(Polynomial, Polynomial), MathError> {
+        let (mut remaining, divisor) = Polynomial::unify(self, divisor);
+
+        let Some((divisor_exponents, divisor_coefficient)) = divisor.leading_term(order) else {
+            return Err(MathError::DivisionByZero);
+        };
+        let divisor_exponents = divisor_exponents.clone();
+
+        let mut quotient = Polynomial::zero(&remaining.variables);
+        let mut remainder = Polynomial::zero(&remaining.variables);
+
+        while let Some((exponents, coefficient)) = remaining.leading_term(order) {
+            let exponents = exponents.clone();
+
+            let divisible = exponents.iter().zip(&divisor_exponents).all(|(a, b)| a >= b);
+            if !divisible {
+                remaining.terms.remove(&exponents);
+                remainder.add_term(exponents, coefficient)?;
+                continue;
+            }
+
+            let mut term = Polynomial::zero(&remaining.variables);
+            term.terms.insert(
+                exponents.iter().zip(&divisor_exponents).map(|(a, b)| a - b).collect(),
+                coefficient.checked_div(divisor_coefficient).ok_or(MathError::Overflow)?,
+            );
+
+            remaining = remaining.try_sub(&term.try_mul(&divisor)?)?;
+            quotient = quotient.try_add(&term)?;
+        }
+
+        Ok((quotient, remainder))
+    }
+
+    //divisor has to divide self exactly, the remainder is dropped
+    pub fn exact_div(&self, divisor: &Polynomial) -> Result<Polynomial, MathError> {
+        Ok(self.div_rem(divisor, MonomialOrder::Lex)?.0)
+    }
+
+    //Scaled to integer coefficients without a common factor and a positive leading coefficient
+    pub fn normalized(&self) -> Result<Polynomial, MathError> {
+        let Some((_, leading)) = self.leading_term(MonomialOrder::Lex) else {
+            return Ok(self.clone());
+        };
+
+        let mut denominator: i128 = 1;
+        let mut numerator: u128 = 0;
+
+        for coefficient in self.terms.values() {
+            let divisor = gcd(denominator as u128, coefficient.denominator() as u128) as i128;
+            denominator = (denominator / divisor).checked_mul(coefficient.denominator()).ok_or(MathError::Overflow)?;
+            numerator = gcd(numerator, coefficient.numerator().unsigned_abs());
+        }
+
+        let numerator = i128::try_from(numerator).map_err(|_| MathError::Overflow)?;
+        let factor = Rational::try_new(if leading.is_negative() { -denominator } else { denominator }, numerator)?;
+
+        self.try_scale(factor)
+    }
+
+    pub(crate) fn main_variable(&self) -> Option<usize> {
+        (0..self.variables.len()).find(|i| self.terms.keys().any(|x| x[*i] > 0))
+    }
+
+    //Greatest common divisor of the coefficients in variables[index]
+    pub(crate) fn content(&self, index: usize) -> Result<Polynomial, MathError> {
+        let mut result = Polynomial::zero(&self.variables);
+
+        for degree in 0..=self.degree(index).unwrap_or(0) {
+            let coefficient = self.coefficient(index, degree);
+            if !coefficient.is_zero() {
+                result = result.gcd(&coefficient)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    //lc(divisor)^k * self = quotient * divisor + remainder with a lower degree in variables[index]
+    fn pseudo_remainder(&self, divisor: &Polynomial, index: usize) -> Result<Polynomial, MathError> {
+        let divisor_degree = divisor.degree(index).unwrap_or(0);
+        let leading = divisor.coefficient(index, divisor_degree);
+
+        let mut remainder = self.clone();
+        while let Some(degree) = remainder.degree(index).filter(|x| *x >= divisor_degree) {
+            let mut shift = vec![0; self.variables.len()];
+            shift[index] = degree - divisor_degree;
+
+            let mut factor = Polynomial::zero(&self.variables);
+            for (exponents, coefficient) in remainder.coefficient(index, degree).terms {
+                factor.add_term(exponents.iter().zip(&shift).map(|(a, b)| a + b).collect(), coefficient)?;
+            }
+
+            remainder = leading.try_mul(&remainder)?.try_sub(&factor.try_mul(divisor)?)?;
+        }
+
+        Ok(remainder)
+    }
+
+    /*
+    Greatest common divisor, normalized like normalized().
+    Recursive in the variables: the contents are handled one variable down,
+    the primitive parts by a primitive pseudo remainder sequence.
+     */
+    pub fn gcd(&self, other: &Polynomial) -> Result<Polynomial, MathError> {
+        let (a, b) = Polynomial::unify(self, other);
+
+        if a.is_zero() {
+            return b.normalized();
+        }
+
+        if b.is_zero() {
+            return a.normalized();
+        }
+
+        let Some(index) = a.main_variable().into_iter().chain(b.main_variable()).min() else {
+            return Ok(Polynomial::constant(&a.variables, Rational::ONE));
+        };
+
+        let (a_content, b_content) = (a.content(index)?, b.content(index)?);
+        let content = a_content.gcd(&b_content)?;
+
+        let mut f = a.exact_div(&a_content)?.normalized()?;
+        let mut g = b.exact_div(&b_content)?.normalized()?;
+        if f.degree(index) < g.degree(index) {
+            (f, g) = (g, f);
+        }
+
+        while !g.is_zero() {
+            let remainder = f.pseudo_remainder(&g, index)?;
+            f = g;
+            //Normalizing also removes integer content, which the content over the other variables misses
+            g = if remainder.is_zero() {
+                remainder
+            } else {
+                remainder.exact_div(&remainder.content(index)?)?.normalized()?
+            };
+        }
+
+        f = f.exact_div(&f.content(index)?)?;
+
+        content.try_mul(&f)?.normalized()
+    }
+
+    pub fn to_math_type(&self) -> MathType {
+        let mut summands = vec![];
+
+        for (exponents, coefficient) in self.sorted_terms(MonomialOrder::GrLex) {
+            let mut factors = vec![];
+            for (i, exponent) in exponents.iter().enumerate() {
+                match exponent {
+                    0 => (),
+                    1 => factors.push(self.variables[i].clone()),
+                    _ => factors.push(math_type::power(self.variables[i].clone(), math_type::natural_number(*exponent as u128))),
+                }
+            }
+
+            let rest = match factors.len() {
+                0 => math_type::product(vec![]),
+                1 => factors.remove(0),
+                _ => math_type::product(factors),
+            };
+
+            summands.push(math_type::with_coefficient(coefficient, rest));
+        }
+
+        match summands.len() {
+            0 => math_type::natural_number(0),
+            1 => summands.remove(0),
+            _ => math_type::sum(summands),
+        }
+    }
+}
+
+impl Add for &Polynomial {
+    type Output = Polynomial;
+
+    fn add(self, other: &Polynomial) -> Polynomial {
+        self.try_add(other).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl Sub for &Polynomial {
+    type Output = Polynomial;
+
+    fn sub(self, other: &Polynomial) -> Polynomial {
+        self.try_sub(other).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl Neg for &Polynomial {
+    type Output = Polynomial;
+
+    fn neg(self) -> Polynomial {
+        self.try_neg().unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl Mul for &Polynomial {
+    type Output = Polynomial;
+
+    fn mul(self, other: &Polynomial) -> Polynomial {
+        self.try_mul(other).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+fn contains_any(tree: &MathType, variables: &[MathType]) -> bool {
+    variables.contains(tree) || tree.parameter.iter().any(|x| contains_any(x, variables))
+}
+
+//Appends unknown atoms to variables, returns None for subterms that are not polynomial in requested
+fn convert(tree: &MathType, requested: &[MathType], variables: &mut Vec<MathType>) -> Option<Polynomial> {
+    if let Some(value) = tree.constant_value() {
+        return Some(Polynomial::constant(variables, value));
+    }
+
+    match tree.type_name {
+        MathTypeName::Undefined => None,
+        MathTypeName::Sum => {
+            let mut result = Polynomial::zero(variables);
+            for para in &tree.parameter {
+                result = result.try_add(&convert(para, requested, variables)?).ok()?;
+            }
+
+            Some(result)
+        },
+        MathTypeName::Product => {
+            let mut result = Polynomial::constant(variables, Rational::ONE);
+            for para in &tree.parameter {
+                result = result.try_mul(&convert(para, requested, variables)?).ok()?;
+            }
+
+            Some(result)
+        },
+        MathTypeName::FlipSign => convert(&tree.parameter[0], requested, variables)?.try_neg().ok(),
+        MathTypeName::Power => {
+            let exponent = tree.parameter[1].constant_value();
+
+            if let Some(exponent) = exponent.filter(|x| x.is_integer() && !x.is_negative()).and_then(|x| u32::try_from(x.numerator()).ok()) {
+                return convert(&tree.parameter[0], requested, variables)?.try_pow(exponent).ok();
+            }
+
+            if let (Some(base), Some(exponent)) = (tree.parameter[0].constant_value(), exponent.filter(|x| x.is_integer())) {
+                return Some(Polynomial::constant(variables, base.pow(i32::try_from(exponent.numerator()).ok()?).ok()?));
+            }
+
+            //x^-1, x^y or 2^x are not polynomial in x, but can be atoms for the other variables
+            non_polynomial_atom(tree, requested, variables)
+        },
+        _ => non_polynomial_atom(tree, requested, variables),
+    }
+}
+
+//exp(x) or f(x) are atoms unless x is requested, a requested subtree itself stays a variable
+fn non_polynomial_atom(tree: &MathType, requested: &[MathType], variables: &mut Vec<MathType>) -> Option<Polynomial> {
+    if !requested.contains(tree) && contains_any(tree, requested) {
+        return None;
+    }
+
+    Some(atom(tree, variables))
+}
+
+fn atom(tree: &MathType, variables: &mut Vec<MathType>) -> Polynomial {
+    let index = match variables.iter().position(|x| x == tree) {
+        Some(index) => index,
+        None => {
+            variables.push(tree.clone());
+            variables.len() - 1
+        },
+    };
+
+    Polynomial::variable(variables, index)
+}
+
+impl MathType {
+    /*
+    Polynomial in vars, followed by every other atom (variables, functions, exp, ln, ...) as an extra variable.
+    None if a requested variable appears anywhere else than in sums, products and non negative integer powers.
+
+    (x + 1)^2 with [x] => x^2 + 2 x + 1 over [x]
+    f'(a) x with [x] => x f'(a) over [x, f'(a)]
+    exp(x) + x^-1 with [x] => None
+     */
+    pub fn to_polynomial(&self, vars: &[MathType]) -> Option<Polynomial> {
+        let mut variables = vars.to_vec();
+
+        //Folds constant subterms like 2^-1 first
+        let result = convert(&self.reduce_neutral(), vars, &mut variables)?;
+
+        Some(result.with_variables(&variables))
+    }
+
+    pub fn cancel(&self) -> MathType {
+        self.try_cancel().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /*
+    Rewrites a rational expression as p / q with gcd(p, q) = 1.
+    Unless q is constant both have integer coefficients and q has a positive leading coefficient.
+    Atoms are cancelled recursively and treated as variables.
+
+    (x^2 - 1) / (x + 1) => x - 1
+    1 / a + 1 / b => (a + b) / (a b)
+    x / (2 y) => x / (2 y)
+     */
+    pub fn try_cancel(&self) -> Result<MathType, MathError> {
+        self.validate()?;
+
+        let mut variables = vec![];
+        let (numerator, denominator) = convert_fraction(&self.reduce_neutral(), &mut variables, &MathType::try_cancel)?;
+        let (numerator, normalized) = reduce_fraction(&numerator, &denominator)?;
+
+        if normalized.constant_value().is_some() {
+            return Ok(numerator.to_math_type());
+        }
+
+        //numerator = (a / b) * primitive, b joins the denominator so both sides have integer coefficients
+        let primitive = numerator.normalized()?;
+        let (_, leading) = numerator.leading_term(MonomialOrder::Lex).unwrap();
+        let (_, primitive_leading) = primitive.leading_term(MonomialOrder::Lex).unwrap();
+        let factor = leading.checked_div(primitive_leading).ok_or(MathError::Overflow)?;
+
+        let numerator = primitive.try_scale(Rational::integer(factor.numerator()))?;
+        let denominator = normalized.try_scale(Rational::integer(factor.denominator()))?;
+
+        Ok(math_type::fraction(numerator.to_math_type(), denominator.to_math_type()))
+    }
+}
+
+//Cancels the gcd, the denominator is normalized and constant factors move to the numerator
+pub(crate) fn reduce_fraction(numerator: &Polynomial, denominator: &Polynomial) -> Result<(Polynomial, Polynomial), MathError> {
+    let (numerator, denominator) = Polynomial::unify(numerator, denominator);
+
+    let divisor = numerator.gcd(&denominator)?;
+    let numerator = numerator.exact_div(&divisor)?;
+    let denominator = denominator.exact_div(&divisor)?;
+
+    let normalized = denominator.normalized()?;
+    let (_, normalized_leading) = normalized.leading_term(MonomialOrder::Lex).ok_or(MathError::DivisionByZero)?;
+    let (_, leading) = denominator.leading_term(MonomialOrder::Lex).unwrap();
+    let numerator = numerator.try_scale(normalized_leading.checked_div(leading).ok_or(MathError::Overflow)?)?;
+
+    Ok((numerator, normalized))
+}
+
+//Normal form of the parameters of atoms like f(..), exp(..) or x^y
+pub(crate) type AtomForm = dyn Fn(&MathType) -> Result<MathType, MathError>;
+
+//(numerator, denominator), unknown atoms are appended to variables
+pub(crate) fn convert_fraction(tree: &MathType, variables: &mut Vec<MathType>, atom_form: &AtomForm) -> Result<(Polynomial, Polynomial), MathError> {
+    if let Some(value) = tree.constant_value() {
+        return Ok((Polynomial::constant(variables, value), Polynomial::constant(variables, Rational::ONE)));
+    }
+
+    match tree.type_name {
+        MathTypeName::Sum => {
+            let mut numerator = Polynomial::zero(variables);
+            let mut denominator = Polynomial::constant(variables, Rational::ONE);
+
+            for para in &tree.parameter {
+                let (para_numerator, para_denominator) = convert_fraction(para, variables, atom_form)?;
+                let (para_denominator, current) = Polynomial::unify(&para_denominator, &denominator);
+
+                if para_denominator == current {
+                    numerator = numerator.try_add(&para_numerator)?;
+                } else {
+                    numerator = numerator.try_mul(&para_denominator)?.try_add(&para_numerator.try_mul(&current)?)?;
+                    denominator = current.try_mul(&para_denominator)?;
+                }
+            }
+
+            Ok((numerator, denominator))
+        },
+        MathTypeName::Product => {
+            let mut numerator = Polynomial::constant(variables, Rational::ONE);
+            let mut denominator = Polynomial::constant(variables, Rational::ONE);
+
+            for para in &tree.parameter {
+                let (para_numerator, para_denominator) = convert_fraction(para, variables, atom_form)?;
+                numerator = numerator.try_mul(&para_numerator)?;
+                denominator = denominator.try_mul(&para_denominator)?;
+            }
+
+            Ok((numerator, denominator))
+        },
+        MathTypeName::FlipSign => {
+            let (numerator, denominator) = convert_fraction(&tree.parameter[0], variables, atom_form)?;
+            Ok((numerator.try_neg()?, denominator))
+        },
+        MathTypeName::Power => {
+            let exponent_form = atom_form(&tree.parameter[1])?;
+            let exponent = exponent_form.constant_value().filter(|x| x.is_integer()).and_then(|x| i32::try_from(x.numerator()).ok());
+
+            let Some(exponent) = exponent else {
+                let base = atom_form(&tree.parameter[0])?;
+                return Ok((atom(&math_type::power(base, exponent_form), variables), Polynomial::constant(variables, Rational::ONE)));
+            };
+
+            let (numerator, denominator) = convert_fraction(&tree.parameter[0], variables, atom_form)?;
+            if exponent >= 0 {
+                return Ok((numerator.try_pow(exponent as u32)?, denominator.try_pow(exponent as u32)?));
+            }
+
+            if numerator.is_zero() {
+                return Err(MathError::DivisionByZero);
+            }
+
+            Ok((denominator.try_pow(exponent.unsigned_abs())?, numerator.try_pow(exponent.unsigned_abs())?))
+        },
+        _ => {
+            let mut parameter = vec![];
+            for para in &tree.parameter {
+                parameter.push(atom_form(para)?);
+            }
+
+            Ok((atom(&MathType::try_new(tree.type_name.clone(), parameter)?, variables), Polynomial::constant(variables, Rational::ONE)))
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn polynomial(input: &str, vars: &[&str]) -> Polynomial {
+        let vars = vars.iter().map(|x| math_type::variable(x)).collect::<Vec<MathType>>();
+        MathType::parse(input).unwrap().to_polynomial(&vars).unwrap()
+    }
+
+    fn cancel(input: &str) -> String {
+        MathType::parse(input).unwrap().cancel().get_typst_string()
+    }
+
+    #[test]
+    fn gcd_of_multivariate_polynomials() {
+        let common = polynomial("3 * x^2 + 5 * x * y - 7", &["x", "y"]);
+        let a = common.try_mul(&polynomial("(2 * x - 11 * y + 13)^3", &["x", "y"])).unwrap();
+        let b = common.try_mul(&polynomial("(5 * x + 3 * y - 1)^4", &["x", "y"])).unwrap();
+
+        assert!(a.gcd(&b).unwrap() == common);
+    }
+
+    #[test]
+    fn gcd_keeps_coefficients_small() {
+        //Without normalizing the pseudo remainders their integer content overflows i128
+        let a = polynomial("(x + 1)^3 * (7 * x^3 - 13 * x + 29)", &["x"]);
+        let b = polynomial("(x + 1)^2 * (11 * x^4 + 17 * x^2 - 23)", &["x"]);
+
+        assert!(a.gcd(&b).unwrap() == polynomial("(x + 1)^2", &["x"]));
+    }
+
+    #[test]
+    fn gcd_is_normalized() {
+        assert!(polynomial("-2 * x - 2", &["x"]).gcd(&polynomial("4 * x + 4", &["x"])).unwrap() == polynomial("x + 1", &["x"]));
+        assert!(polynomial("x", &["x"]).gcd(&polynomial("y", &["x", "y"])).unwrap() == polynomial("1", &["x", "y"]));
+    }
+
+    #[test]
+    fn cancels_to_a_single_fraction() {
+        assert_eq!(cancel("(x^2 - 1) / (x + 1)"), "x + -1");
+        assert_eq!(cancel("1 / a + 1 / b"), "frac(a + b, a b)");
+        assert_eq!(cancel("x / (2 * y)"), "frac(x, 2 y)");
+        assert_eq!(cancel("-x / (2 * y)"), "frac(-x, 2 y)");
+        assert_eq!(cancel("(x / 3 + 1 / 2) / (y / 4)"), "frac(4 x + 6, 3 y)");
+        assert_eq!(cancel("x / 2"), "frac(1, 2) x");
+    }
+
+    fn exponents(poly: &Polynomial, order: MonomialOrder) -> Vec<Vec<u32>> {
+        poly.sorted_terms(order).into_iter().map(|(x, _)| x.clone()).collect()
+    }
+
+    #[test]
+    fn monomial_orders() {
+        let poly = polynomial("4 * x * y^2 * z + 4 * z^2 - 5 * x^3 + 7 * x^2 * z^2", &["x", "y", "z"]);
+
+        assert_eq!(exponents(&poly, MonomialOrder::Lex), vec![vec![3, 0, 0], vec![2, 0, 2], vec![1, 2, 1], vec![0, 0, 2]]);
+        assert_eq!(exponents(&poly, MonomialOrder::GrLex), vec![vec![2, 0, 2], vec![1, 2, 1], vec![3, 0, 0], vec![0, 0, 2]]);
+        assert_eq!(exponents(&poly, MonomialOrder::GrevLex), vec![vec![1, 2, 1], vec![2, 0, 2], vec![3, 0, 0], vec![0, 0, 2]]);
+
+        assert!(poly.leading_term(MonomialOrder::Lex) == Some((&vec![3, 0, 0], Rational::integer(-5))));
+        assert!(poly.leading_term(MonomialOrder::GrevLex) == Some((&vec![1, 2, 1], Rational::integer(4))));
+    }
+
+    #[test]
+    fn grlex_and_grevlex_differ_on_ties() {
+        //Same total degree, x y z^2 is bigger in GrLex because of x, smaller in GrevLex because of z
+        let a = [1, 1, 2];
+        let b = [0, 3, 1];
+
+        assert_eq!(MonomialOrder::GrLex.compare(&a, &b), Ordering::Greater);
+        assert_eq!(MonomialOrder::GrevLex.compare(&a, &b), Ordering::Less);
+        assert_eq!(MonomialOrder::Lex.compare(&[0, 5, 5], &[1, 0, 0]), Ordering::Less);
+    }
+
+    #[test]
+    fn round_trip_through_math_type() {
+        let env = HashMap::from([("x".to_string(), 0.3), ("y".to_string(), -1.7), ("a".to_string(), 2.1), ("b".to_string(), 0.4)]);
+
+        for input in ["(x + 1)^2", "(x - y)^3 * exp(a) + 1 / 2", "x * exp(a) - ln(b) * y^2", "0", "-3"] {
+            let poly = polynomial(input, &["x", "y"]);
+            let tree = poly.to_math_type();
+
+            //Atoms can come back in a different order
+            let back = tree.to_polynomial(&[math_type::variable("x"), math_type::variable("y")]).unwrap();
+            assert!(back.with_variables(poly.variables()) == poly, "{}", input);
+
+            let expected = MathType::parse(input).unwrap().eval(&env).unwrap();
+            assert!((tree.eval(&env).unwrap() - expected).abs() < 1e-9, "{}", input);
+        }
+
+        assert_eq!(polynomial("(x + 1)^2", &["x"]).to_math_type().get_typst_string(), "(x)^(2) + 2 x + 1");
+    }
+
+    #[test]
+    fn requested_variables_only_in_polynomial_positions() {
+        let x = [math_type::variable("x")];
+
+        for input in ["exp(x)", "f(x) + 1", "ln(x * y)", "x^-1", "x^y", "2^x", "f(g(x))"] {
+            assert!(MathType::parse(input).unwrap().to_polynomial(&x).is_none(), "{}", input);
+        }
+
+        let poly = MathType::parse("exp(y) * x + f(y)").unwrap().to_polynomial(&x).unwrap();
+        assert_eq!(poly.variables().len(), 3);
+
+        //A requested subtree is a variable even if it contains other requested variables
+        let vars = [math_type::variable("x"), MathType::parse("f(x)").unwrap()];
+        assert!(MathType::parse("f(x)^2 + x").unwrap().to_polynomial(&vars).is_some());
+    }
+}
@@ -0,0 +1,272 @@
+use std::{cmp::Ordering, fmt, ops::{Add, Div, Mul, Neg, Sub}};
+
+use super::math_error::MathError;
+
+//Always normalized: denominator > 0 and gcd(numerator, denominator) = 1
+//The numerator is never i128::MIN, so negation and abs can not overflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+pub fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+
+    a
+}
+
+impl Rational {
+    pub const ZERO: Rational = Rational { numerator: 0, denominator: 1 };
+    pub const ONE: Rational = Rational { numerator: 1, denominator: 1 };
+
+    pub fn new(numerator: i128, denominator: i128) -> Rational {
+        Rational::try_new(numerator, denominator).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new(numerator: i128, denominator: i128) -> Result<Rational, MathError> {
+        if denominator == 0 {
+            return Err(MathError::DivisionByZero);
+        }
+
+        let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs());
+        let negative = (numerator < 0) != (denominator < 0);
+
+        let numerator = i128::try_from(numerator.unsigned_abs() / divisor).map_err(|_| MathError::Overflow)?;
+        let denominator = i128::try_from(denominator.unsigned_abs() / divisor).map_err(|_| MathError::Overflow)?;
+
+        Ok(Rational {
+            numerator: if negative { -numerator } else { numerator },
+            denominator,
+        })
+    }
+
+    pub fn integer(value: i128) -> Rational {
+        Rational::try_integer(value).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_integer(value: i128) -> Result<Rational, MathError> {
+        if value == i128::MIN {
+            return Err(MathError::Overflow);
+        }
+
+        Ok(Rational {
+            numerator: value,
+            denominator: 1,
+        })
+    }
+
+    pub fn numerator(&self) -> i128 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i128 {
+        self.denominator
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    pub fn is_one(&self) -> bool {
+        self.numerator == 1 && self.denominator == 1
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == 1
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.numerator < 0
+    }
+
+    pub fn abs(&self) -> Rational {
+        Rational {
+            numerator: self.numerator.abs(),
+            denominator: self.denominator,
+        }
+    }
+
+    pub fn recip(&self) -> Result<Rational, MathError> {
+        Rational::try_new(self.denominator, self.numerator)
+    }
+
+    pub fn pow(&self, exponent: i32) -> Result<Rational, MathError> {
+        let mut base = if exponent < 0 { self.recip()? } else { *self };
+        let mut exponent = exponent.unsigned_abs();
+        let mut result = Rational::ONE;
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.checked_mul(base).ok_or(MathError::Overflow)?;
+            }
+
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.checked_mul(base).ok_or(MathError::Overflow)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn checked_add(self, other: Rational) -> Option<Rational> {
+        let divisor = gcd(self.denominator as u128, other.denominator as u128) as i128;
+
+        let left = self.numerator.checked_mul(other.denominator / divisor)?;
+        let right = other.numerator.checked_mul(self.denominator / divisor)?;
+        let denominator = (self.denominator / divisor).checked_mul(other.denominator)?;
+
+        Rational::try_new(left.checked_add(right)?, denominator).ok()
+    }
+
+    pub fn checked_sub(self, other: Rational) -> Option<Rational> {
+        self.checked_add(other.checked_neg()?)
+    }
+
+    pub fn checked_neg(self) -> Option<Rational> {
+        Some(Rational {
+            numerator: self.numerator.checked_neg()?,
+            denominator: self.denominator,
+        })
+    }
+
+    pub fn checked_mul(self, other: Rational) -> Option<Rational> {
+        //Cross reduce first to keep the intermediate products small
+        let left = gcd(self.numerator.unsigned_abs(), other.denominator as u128) as i128;
+        let right = gcd(other.numerator.unsigned_abs(), self.denominator as u128) as i128;
+
+        let numerator = (self.numerator / left).checked_mul(other.numerator / right)?;
+        let denominator = (self.denominator / right).checked_mul(other.denominator / left)?;
+
+        Rational::try_new(numerator, denominator).ok()
+    }
+
+    pub fn checked_div(self, other: Rational) -> Option<Rational> {
+        self.checked_mul(other.recip().ok()?)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl TryFrom<u128> for Rational {
+    type Error = MathError;
+
+    fn try_from(value: u128) -> Result<Self, Self::Error> {
+        Rational::try_integer(i128::try_from(value).map_err(|_| MathError::Overflow)?)
+    }
+}
+
+impl Add for Rational {
+    type Output = Rational;
+
+    fn add(self, other: Rational) -> Rational {
+        self.checked_add(other).expect("Rational overflow")
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        self.checked_neg().expect("Rational overflow")
+    }
+}
+
+impl Sub for Rational {
+    type Output = Rational;
+
+    fn sub(self, other: Rational) -> Rational {
+        self.checked_sub(other).expect("Rational overflow")
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, other: Rational) -> Rational {
+        self.checked_mul(other).expect("Rational overflow")
+    }
+}
+
+impl Div for Rational {
+    type Output = Rational;
+
+    fn div(self, other: Rational) -> Rational {
+        self.checked_div(other).expect("Rational overflow or division by zero")
+    }
+}
+
+impl Ord for Rational {
+    //Compares the integer parts, then the reciprocals of the remainders, so nothing can overflow
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.denominator == other.denominator {
+            return self.numerator.cmp(&other.numerator);
+        }
+
+        let self_integer = self.numerator.div_euclid(self.denominator);
+        let other_integer = other.numerator.div_euclid(other.denominator);
+
+        if self_integer != other_integer {
+            return self_integer.cmp(&other_integer);
+        }
+
+        let self_remainder = self.numerator.rem_euclid(self.denominator);
+        let other_remainder = other.numerator.rem_euclid(other.denominator);
+
+        if self_remainder == 0 || other_remainder == 0 {
+            return self_remainder.cmp(&other_remainder);
+        }
+
+        let self_reciprocal = Rational { numerator: self.denominator, denominator: self_remainder };
+        let other_reciprocal = Rational { numerator: other.denominator, denominator: other_remainder };
+
+        other_reciprocal.cmp(&self_reciprocal)
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            return write!(f, "{}", self.numerator);
+        }
+
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_minimum_numerator() {
+        assert_eq!(Rational::try_integer(i128::MIN), Err(MathError::Overflow));
+        assert_eq!(Rational::try_new(i128::MIN, 1), Err(MathError::Overflow));
+        assert_eq!(Rational::try_new(i128::MIN, 2).unwrap().abs(), Rational::integer(1 << 126));
+        assert_eq!(Rational::integer(i128::MIN + 1).abs(), Rational::integer(i128::MAX));
+    }
+
+    #[test]
+    fn divides() {
+        assert_eq!(Rational::new(3, 4) / Rational::new(-1, 2), Rational::new(-3, 2));
+        assert_eq!(Rational::ONE.checked_div(Rational::ZERO), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn division_by_zero_panics() {
+        let _ = Rational::ONE / Rational::ZERO;
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use super::{math_error::MathError, math_type::MathType, math_type_name::MathTypeName};
+
+//Pattern variable name (including the '?') to the matched subtree
+pub type Bindings = HashMap<String, MathType>;
+pub type Guard = dyn Fn(&Bindings) -> bool;
+
+/*
+lhs => rhs, where names starting with '?' are pattern variables that match any subtree.
+A variable used twice has to match equal subtrees.
+
+Sum and product patterns match their parameters in any order.
+At the root of a match they may also cover only part of the parameters, the rest is kept:
+exp(?a) * exp(?b) => exp(?a + ?b) rewrites 2 * exp(x) * y * exp(z) to 2 * exp(x + z) * y
+ */
+pub struct Rule {
+    pub name: String,
+    lhs: MathType,
+    rhs: MathType,
+    guard: Option<Box<Guard>>,
+}
+
+impl Rule {
+    pub fn new(name: &str, lhs: MathType, rhs: MathType) -> Result<Rule, MathError> {
+        lhs.validate()?;
+        rhs.validate()?;
+
+        let mut bound = vec![];
+        collect_pattern_variables(&lhs, &mut bound);
+
+        let mut used = vec![];
+        collect_pattern_variables(&rhs, &mut used);
+
+        if let Some(name) = used.into_iter().find(|x| !bound.contains(x)) {
+            return Err(MathError::UnboundPatternVariable(name));
+        }
+
+        Ok(Rule {
+            name: name.to_string(),
+            lhs,
+            rhs,
+            guard: None,
+        })
+    }
+
+    pub fn parse(name: &str, lhs: &str, rhs: &str) -> Result<Rule, MathError> {
+        Rule::new(name, MathType::parse(lhs)?, MathType::parse(rhs)?)
+    }
+
+    //The rule only applies to matches the guard accepts
+    pub fn with_guard(mut self, guard: impl Fn(&Bindings) -> bool + 'static) -> Rule {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    pub fn lhs(&self) -> &MathType {
+        &self.lhs
+    }
+
+    pub fn rhs(&self) -> &MathType {
+        &self.rhs
+    }
+
+    fn accepts(&self, bindings: &Bindings) -> bool {
+        self.guard.as_ref().is_none_or(|guard| guard(bindings))
+    }
+
+    //Rewrites tree if the rule matches at its root
+    pub fn apply(&self, tree: &MathType) -> Option<MathType> {
+        let mut bindings = Bindings::new();
+        let mut result = None;
+
+        let partial = tree.type_name.is_commutative()
+            && self.lhs.type_name == tree.type_name
+            && self.lhs.parameter.len() < tree.parameter.len();
+
+        if partial {
+            let patterns = pattern_order(&self.lhs.parameter);
+            let mut used = vec![false; tree.parameter.len()];
+
+            match_unordered(&patterns, &tree.parameter, &mut used, &mut bindings, &mut |bindings, used| {
+                if !self.accepts(bindings) {
+                    return false;
+                }
+
+                let mut parameter = vec![instantiate(&self.rhs, bindings)];
+                parameter.extend(tree.parameter.iter().zip(used).filter(|(_, used)| !**used).map(|(x, _)| x.clone()));
+
+                result = Some(MathType::new(tree.type_name.clone(), parameter));
+                true
+            });
+        } else {
+            match_node(&self.lhs, tree, &mut bindings, &mut |bindings| {
+                if !self.accepts(bindings) {
+                    return false;
+                }
+
+                result = Some(instantiate(&self.rhs, bindings));
+                true
+            });
+        }
+
+        result
+    }
+}
+
+//Identities the fixed simplify pipeline does not know about
+pub fn standard_rules() -> Vec<Rule> {
+    [
+        ("exp_ln", "exp(ln(?x))", "?x"),
+        ("ln_exp", "ln(exp(?x))", "?x"),
+        ("exp_zero", "exp(0)", "1"),
+        ("ln_one", "ln(1)", "0"),
+        ("exp_product", "exp(?a) * exp(?b)", "exp(?a + ?b)"),
+        ("exp_power", "exp(?a)^?n", "exp(?n * ?a)"),
+    ].iter().map(|(name, lhs, rhs)| Rule::parse(name, lhs, rhs).unwrap()).collect()
+}
+
+fn pattern_variable(tree: &MathType) -> Option<&str> {
+    match tree.type_name {
+        MathTypeName::Function(ref name) if name.starts_with('?') && tree.parameter.is_empty() => Some(name),
+        _ => None,
+    }
+}
+
+fn collect_pattern_variables(tree: &MathType, result: &mut Vec<String>) {
+    if let Some(name) = pattern_variable(tree) {
+        if !result.iter().any(|x| x == name) {
+            result.push(name.to_string());
+        }
+
+        return;
+    }
+
+    for para in &tree.parameter {
+        collect_pattern_variables(para, result);
+    }
+}
+
+//Structured patterns first, so unbound variables are tried last and prune less
+fn pattern_order(patterns: &[MathType]) -> Vec<MathType> {
+    let mut result = patterns.to_vec();
+    result.sort_by_key(|x| pattern_variable(x).is_some());
+    result
+}
+
+//Calls found for every way pattern matches tree, stops as soon as found returns true
+fn match_node(pattern: &MathType, tree: &MathType, bindings: &mut Bindings, found: &mut dyn FnMut(&mut Bindings) -> bool) -> bool {
+    if let Some(name) = pattern_variable(pattern) {
+        if let Some(bound) = bindings.get(name) {
+            return *bound == *tree && found(bindings);
+        }
+
+        bindings.insert(name.to_string(), tree.clone());
+        let result = found(bindings);
+        bindings.remove(name);
+
+        return result;
+    }
+
+    if pattern.type_name != tree.type_name || pattern.parameter.len() != tree.parameter.len() {
+        return false;
+    }
+
+    if pattern.type_name.is_commutative() {
+        let patterns = pattern_order(&pattern.parameter);
+        let mut used = vec![false; tree.parameter.len()];
+
+        return match_unordered(&patterns, &tree.parameter, &mut used, bindings, &mut |bindings, _| found(bindings));
+    }
+
+    match_sequence(&pattern.parameter, &tree.parameter, bindings, found)
+}
+
+fn match_sequence(patterns: &[MathType], trees: &[MathType], bindings: &mut Bindings, found: &mut dyn FnMut(&mut Bindings) -> bool) -> bool {
+    if patterns.is_empty() {
+        return found(bindings);
+    }
+
+    match_node(&patterns[0], &trees[0], bindings, &mut |bindings| match_sequence(&patterns[1..], &trees[1..], bindings, found))
+}
+
+//Assigns every pattern to a different unused tree, found also gets the trees that were used
+fn match_unordered(patterns: &[MathType], trees: &[MathType], used: &mut Vec<bool>, bindings: &mut Bindings, found: &mut dyn FnMut(&mut Bindings, &[bool]) -> bool) -> bool {
+    if patterns.is_empty() {
+        return found(bindings, used);
+    }
+
+    for i in 0..trees.len() {
+        if used[i] {
+            continue;
+        }
+
+        used[i] = true;
+        let matched = match_node(&patterns[0], &trees[i], bindings, &mut |bindings| match_unordered(&patterns[1..], trees, used, bindings, found));
+        used[i] = false;
+
+        if matched {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn instantiate(tree: &MathType, bindings: &Bindings) -> MathType {
+    if let Some(name) = pattern_variable(tree) {
+        return bindings[name].clone();
+    }
+
+    MathType::new(tree.type_name.clone(), tree.parameter.iter().map(|x| instantiate(x, bindings)).collect())
+}
+
+impl MathType {
+    //One bottom up pass, every node is rewritten by the first rule that matches it
+    pub fn rewrite(&self, rules: &[Rule]) -> MathType {
+        let tree = MathType::new(self.type_name.clone(), self.parameter.iter().map(|x| x.rewrite(rules)).collect());
+
+        for rule in rules {
+            if let Some(result) = rule.apply(&tree) {
+                return result;
+            }
+        }
+
+        tree
+    }
+
+    pub fn simplify_with(&self, rules: &[Rule]) -> MathType {
+        self.try_simplify_with(rules).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    //Same pipeline as simplify with the rules applied after every round
+    pub fn try_simplify_with(&self, rules: &[Rule]) -> Result<MathType, MathError> {
+        self.validate()?;
+
+        let mut prev = self.clone();
+        for _ in 0..90 {
+            let mut next = prev.expand().flatten().reduce_neutral().combine().rewrite(rules);
+            next.sort();
+
+            if next == prev {
+                break;
+            }
+
+            prev = next;
+        }
+
+        Ok(prev)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> MathType {
+        MathType::parse(input).unwrap()
+    }
+
+    fn rule(lhs: &str, rhs: &str) -> Rule {
+        Rule::parse("test", lhs, rhs).unwrap()
+    }
+
+    #[test]
+    fn repeated_variables_bind_the_same_subtree() {
+        let cancel = rule("?a - ?a", "0");
+        assert!(cancel.apply(&parse("x * y - y * x")).unwrap() == parse("0"));
+        assert!(cancel.apply(&parse("x - y")).is_none());
+
+        let diagonal = rule("f(?a, ?a)", "g(?a)");
+        assert!(diagonal.apply(&parse("f(x + 1, 1 + x)")).unwrap() == parse("g(x + 1)"));
+        assert!(diagonal.apply(&parse("f(x, y)")).is_none());
+    }
+
+    #[test]
+    fn sums_and_products_match_in_any_order() {
+        let exp_product = rule("exp(?a) * exp(?b)", "exp(?a + ?b)");
+        assert!(exp_product.apply(&parse("exp(y) * exp(x)")).unwrap() == parse("exp(x + y)"));
+
+        let pythagoras = rule("f(?a)^2 + g(?a)^2", "1");
+        assert!(pythagoras.apply(&parse("g(x)^2 + f(x)^2")).unwrap() == parse("1"));
+        assert!(pythagoras.apply(&parse("g(x)^2 + f(y)^2")).is_none());
+    }
+
+    #[test]
+    fn sums_and_products_match_partially_at_the_root() {
+        let exp_product = rule("exp(?a) * exp(?b)", "exp(?a + ?b)");
+        assert!(exp_product.apply(&parse("2 * exp(x) * y * exp(z)")).unwrap() == parse("exp(x + z) * 2 * y"));
+
+        let pythagoras = rule("f(?a)^2 + g(?a)^2", "1");
+        assert!(pythagoras.apply(&parse("f(x)^2 + 3 + g(x)^2")).unwrap() == parse("1 + 3"));
+
+        //Below the root the whole node has to match
+        assert!(exp_product.apply(&parse("f(2 * exp(x) * exp(z))")).is_none());
+    }
+
+    #[test]
+    fn guards_reject_matches() {
+        let zero_power = rule("?a^?n", "1").with_guard(|bindings| bindings["?n"].constant_value().is_some_and(|x| x.is_zero()));
+        assert!(zero_power.apply(&parse("x^0")).unwrap() == parse("1"));
+        assert!(zero_power.apply(&parse("x^2")).is_none());
+    }
+
+    #[test]
+    fn rewrite_is_bottom_up() {
+        assert!(parse("ln(exp(ln(exp(x))))").rewrite(&standard_rules()) == parse("x"));
+        assert!(parse("f(exp(ln(y)), ln(1))").rewrite(&standard_rules()) == parse("f(y, 0)"));
+    }
+
+    #[test]
+    fn simplify_with_standard_rules() {
+        assert!(parse("exp(ln(x + 1)) - x").simplify_with(&standard_rules()) == parse("1"));
+        assert!(parse("exp(x) * exp(y) * exp(-y)").simplify_with(&standard_rules()) == parse("exp(x)"));
+        assert!(parse("exp(x)^2 * exp(-2 * x)").simplify_with(&standard_rules()) == parse("1"));
+    }
+
+    #[test]
+    fn rejects_unbound_variables() {
+        let result = Rule::parse("bad", "f(?a)", "?a + ?b");
+        assert!(matches!(result, Err(MathError::UnboundPatternVariable(name)) if name == "?b"));
+    }
+}
@@ -0,0 +1,126 @@
+use super::{math_error::MathError, math_type::{self, MathType}, rational::Rational, rewrite::standard_rules};
+
+impl MathType {
+    pub fn taylor(&self, var: &MathType, point: &MathType, order: u32) -> MathType {
+        self.try_taylor(var, point, order).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /*
+    Truncated Taylor series around var = point, a polynomial in (var - point):
+
+    exp(x) around 0 to order 2 => 1 + x + 1/2 x^2
+     */
+    pub fn try_taylor(&self, var: &MathType, point: &MathType, order: u32) -> Result<MathType, MathError> {
+        self.try_taylor_multivariate(&[(var.clone(), point.clone())], order)
+    }
+
+    pub fn taylor_multivariate(&self, points: &[(MathType, MathType)], order: u32) -> MathType {
+        self.try_taylor_multivariate(points, order).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /*
+    Expansion in every (var, point) pair up to the given total order, the sum of all exponents of a term is at most order.
+    Every partial derivative is derived once from its parent and simplified before it is derived further.
+     */
+    pub fn try_taylor_multivariate(&self, points: &[(MathType, MathType)], order: u32) -> Result<MathType, MathError> {
+        for (var, point) in points {
+            if !var.is_variable() {
+                return Err(MathError::NonVariableDerivative(var.get_string()));
+            }
+
+            point.validate()?;
+        }
+
+        let mut terms = vec![];
+        let mut exponents = vec![0; points.len()];
+        taylor_terms(&self.try_simplify()?, points, order, 0, Rational::ONE, &mut exponents, &mut terms)?;
+
+        //The values at the point are often exp(0) or ln(1)
+        math_type::sum(terms).try_simplify_with(&standard_rules())
+    }
+}
+
+//Pushes the term of the current exponents and recurses into every variable from first on, so every multi index is visited once
+fn taylor_terms(derivative: &MathType, points: &[(MathType, MathType)], remaining: u32, first: usize, factorial: Rational,
+    exponents: &mut Vec<u32>, terms: &mut Vec<MathType>) -> Result<(), MathError> {
+    //All at once, a point may contain another expansion variable
+    let value = derivative.substitute(&points.iter().cloned().collect());
+
+    let mut term = vec![math_type::rational(factorial.recip()?), value];
+    for ((var, point), exponent) in points.iter().zip(exponents.iter()) {
+        if *exponent > 0 {
+            let offset = math_type::sum(vec![var.clone(), math_type::flip_sign(point.clone())]);
+            term.push(math_type::power(offset, math_type::natural_number(*exponent as u128)));
+        }
+    }
+
+    terms.push(math_type::product(term));
+
+    if remaining == 0 {
+        return Ok(());
+    }
+
+    for i in first..points.len() {
+        exponents[i] += 1;
+
+        let factorial = factorial.checked_mul(Rational::integer(exponents[i] as i128)).ok_or(MathError::Overflow)?;
+        let next = derivative.try_get_derivative(&points[i].0)?.try_simplify()?;
+        taylor_terms(&next, points, remaining - 1, i, factorial, exponents, terms)?;
+
+        exponents[i] -= 1;
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::math_types::polynomial::Polynomial;
+
+    use super::*;
+
+    fn polynomial(tree: &MathType, vars: &[&str]) -> Polynomial {
+        let vars = vars.iter().map(|x| math_type::variable(x)).collect::<Vec<MathType>>();
+        tree.to_polynomial(&vars).unwrap()
+    }
+
+    fn expands_to(tree: &str, points: &[(&str, &str)], order: u32, expected: &str) -> bool {
+        let points = points.iter().map(|(var, point)| (math_type::variable(var), MathType::parse(point).unwrap())).collect::<Vec<(MathType, MathType)>>();
+        let vars = ["x", "y"];
+
+        let series = MathType::parse(tree).unwrap().taylor_multivariate(&points, order);
+        polynomial(&series, &vars) == polynomial(&MathType::parse(expected).unwrap(), &vars)
+    }
+
+    #[test]
+    fn exp_around_zero() {
+        assert!(expands_to("exp(x)", &[("x", "0")], 3, "1 + x + 1/2 * x^2 + 1/6 * x^3"));
+        assert!(expands_to("exp(2 * x)", &[("x", "0")], 2, "1 + 2 * x + 2 * x^2"));
+    }
+
+    #[test]
+    fn polynomial_around_one() {
+        assert!(expands_to("x^3", &[("x", "1")], 3, "x^3"));
+        assert!(expands_to("x^3", &[("x", "1")], 2, "1 + 3 * (x - 1) + 3 * (x - 1)^2"));
+        assert!(expands_to("x^3", &[("x", "1")], 0, "1"));
+    }
+
+    #[test]
+    fn multivariate() {
+        assert!(expands_to("exp(x) * y", &[("x", "0"), ("y", "1")], 2, "1 + x + (y - 1) + 1/2 * x^2 + x * (y - 1)"));
+        assert!(expands_to("x^2 * y^2", &[("x", "0"), ("y", "0")], 3, "0"));
+    }
+
+    #[test]
+    fn points_are_inserted_at_once() {
+        //x -> y must not be followed by y -> 1
+        assert!(expands_to("x + y", &[("x", "y"), ("y", "1")], 0, "y + 1"));
+    }
+
+    #[test]
+    fn rejects_non_variables() {
+        let result = MathType::parse("x").unwrap().try_taylor(&MathType::parse("x + 1").unwrap(), &math_type::natural_number(0), 1);
+        assert!(matches!(result, Err(MathError::NonVariableDerivative(_))));
+    }
+}
@@ -0,0 +1,542 @@
+use super::{math_type::{self, MathType}, math_type_name::MathTypeName, parser::{read_attachment, ParseError}, rational::Rational, typst_symbols};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u128),
+    Name(String),
+    Text(String),
+    Symbol(char),
+    End,
+}
+
+struct Lexeme {
+    token: Token,
+    position: usize,
+    //Whether whitespace separates this token from the previous one
+    spaced: bool,
+}
+
+/*
+Identifiers follow the same rules as the plain parser:
+theta^1_(i 1) => name
+x^2 => power
+Big operators keep all their limits:
+sum_(i)^(M) => name
+ */
+fn tokenize(input: &str) -> Result<Vec<Lexeme>, ParseError> {
+    let chars = input.chars().collect::<Vec<char>>();
+    let mut lexemes = vec![];
+    let mut spaced = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            spaced = true;
+            i += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+
+            let text = chars[start..i].iter().collect::<String>();
+            Token::Number(text.parse::<u128>().map_err(|_| ParseError::new(start, "Number literal too large"))?)
+        }
+        else if c.is_alphabetic() {
+            i += 1;
+            while i < chars.len() && (chars[i].is_alphanumeric() || (chars[i] == '.' && i + 1 < chars.len() && chars[i + 1].is_alphabetic())) {
+                i += 1;
+            }
+
+            let word = chars[start..i].iter().collect::<String>();
+
+            while i < chars.len() && chars[i] == '\'' {
+                i += 1;
+            }
+
+            let big_operator = typst_symbols::BIG_OPERATORS.contains(&word.as_str());
+
+            loop {
+                if i < chars.len() && chars[i] == '_' {
+                    i = read_attachment(&chars, i + 1)?;
+                    continue;
+                }
+
+                if i < chars.len() && chars[i] == '^' {
+                    if let Ok(end) = read_attachment(&chars, i + 1) {
+                        if big_operator || (end < chars.len() && chars[end] == '_') {
+                            i = end;
+                            continue;
+                        }
+                    }
+                }
+
+                break;
+            }
+
+            Token::Name(chars[start..i].iter().collect())
+        }
+        else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+
+            if i == chars.len() {
+                return Err(ParseError::new(start, "Unclosed string"));
+            }
+
+            i += 1;
+            Token::Text(chars[start + 1..i - 1].iter().collect())
+        }
+        else if "+-/^(),=".contains(c) {
+            i += 1;
+            Token::Symbol(c)
+        }
+        else {
+            return Err(ParseError::new(i, &format!("Unexpected character '{}'", c)));
+        };
+
+        lexemes.push(Lexeme { token, position: start, spaced });
+        spaced = false;
+    }
+
+    lexemes.push(Lexeme { token: Token::End, position: chars.len(), spaced });
+    Ok(lexemes)
+}
+
+struct Parser {
+    lexemes: Vec<Lexeme>,
+    index: usize,
+}
+
+/*
+sum := unary (('+' | '-') unary)*
+unary := '-' unary | product
+product := fraction (('dot' | 'times')? fraction)*
+fraction := attached ('/' attached)*
+attached := atom ('^' attachment)?
+atom := number | name | name '(' list ')' | '(' sum ')' | frac(a, b) | ln(a) | e^a | bigop '(' list ')' | bigop product | "zero" | "one"
+ */
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.lexemes[self.index].token
+    }
+
+    fn position(&self) -> usize {
+        self.lexemes[self.index].position
+    }
+
+    fn spaced(&self) -> bool {
+        self.lexemes[self.index].spaced
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.lexemes[self.index].token.clone();
+        if self.index < self.lexemes.len() - 1 {
+            self.index += 1;
+        }
+
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        if *self.peek() != Token::Symbol(symbol) {
+            return Err(ParseError::new(self.position(), &format!("Expected '{}'", symbol)));
+        }
+
+        self.next();
+        Ok(())
+    }
+
+    fn at_factor_start(&self) -> bool {
+        match self.peek() {
+            Token::Number(_) | Token::Name(_) | Token::Text(_) => true,
+            Token::Symbol(c) => *c == '(',
+            Token::End => false,
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<MathType, ParseError> {
+        let mut summands = vec![self.parse_unary()?];
+
+        loop {
+            match self.peek() {
+                Token::Symbol('+') => {
+                    self.next();
+                    summands.push(self.parse_unary()?);
+                },
+                Token::Symbol('-') => {
+                    self.next();
+                    summands.push(math_type::flip_sign(self.parse_unary()?));
+                },
+                _ => break,
+            }
+        }
+
+        if summands.len() == 1 {
+            return Ok(summands.remove(0));
+        }
+
+        Ok(math_type::sum(summands))
+    }
+
+    fn parse_unary(&mut self) -> Result<MathType, ParseError> {
+        if *self.peek() == Token::Symbol('-') {
+            self.next();
+            return Ok(math_type::flip_sign(self.parse_unary()?));
+        }
+
+        self.parse_product()
+    }
+
+    fn parse_product(&mut self) -> Result<MathType, ParseError> {
+        let mut factors = vec![self.parse_fraction()?];
+
+        loop {
+            if let Token::Name(name) = self.peek() {
+                if typst_symbols::MULTIPLICATION.contains(&name.as_str()) {
+                    self.next();
+                    factors.push(self.parse_fraction()?);
+                    continue;
+                }
+            }
+
+            if !self.at_factor_start() {
+                break;
+            }
+
+            //n^ell_i (theta, x) is a call even though it is separated by whitespace
+            if *self.peek() == Token::Symbol('(') && factors.last().unwrap().is_variable() {
+                let start = self.index;
+                let parameter = self.parse_list()?;
+
+                if parameter.len() > 1 {
+                    let name = match factors.pop().unwrap().type_name {
+                        MathTypeName::Function(name) => name,
+                        _ => unreachable!(),
+                    };
+
+                    factors.push(math_type::function(&name, parameter));
+                    continue;
+                }
+
+                self.index = start;
+            }
+
+            factors.push(self.parse_fraction()?);
+        }
+
+        if factors.len() == 1 {
+            return Ok(factors.remove(0));
+        }
+
+        Ok(math_type::product(factors))
+    }
+
+    fn parse_fraction(&mut self) -> Result<MathType, ParseError> {
+        let mut numerator = self.parse_attached()?;
+
+        while *self.peek() == Token::Symbol('/') {
+            self.next();
+            numerator = build_fraction(numerator, self.parse_attached()?);
+        }
+
+        Ok(numerator)
+    }
+
+    fn parse_attached(&mut self) -> Result<MathType, ParseError> {
+        let base = self.parse_atom()?;
+
+        if *self.peek() == Token::Symbol('^') {
+            self.next();
+            return Ok(math_type::power(base, self.parse_attachment()?));
+        }
+
+        Ok(base)
+    }
+
+    //A parenthesized group or a single token, like the argument of ^ in Typst, x^-1 is allowed as well
+    fn parse_attachment(&mut self) -> Result<MathType, ParseError> {
+        let position = self.position();
+
+        match self.next() {
+            Token::Symbol('-') => Ok(math_type::flip_sign(self.parse_attachment()?)),
+            Token::Symbol('(') => {
+                let inner = self.parse_sum()?;
+                self.expect(')')?;
+                Ok(inner)
+            },
+            Token::Number(value) => Ok(math_type::natural_number(value)),
+            Token::Name(name) => Ok(math_type::variable(&name)),
+            _ => Err(ParseError::new(position, "Expected attachment")),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<MathType, ParseError> {
+        let position = self.position();
+
+        match self.next() {
+            Token::Number(value) => Ok(math_type::natural_number(value)),
+            Token::Name(name) => {
+                if typst_symbols::BIG_OPERATORS.iter().any(|op| name.starts_with(op) && !name[op.len()..].starts_with(char::is_alphanumeric)) {
+                    //sum_(i)^(M)(x_i) y as printed by get_typst_string, the group directly after the operator is its argument
+                    if *self.peek() == Token::Symbol('(') && !self.spaced() {
+                        return build_function(&name, self.parse_list()?, position);
+                    }
+
+                    return Ok(math_type::function(&name, vec![self.parse_product()?]));
+                }
+
+                if name == typst_symbols::EULER && *self.peek() == Token::Symbol('^') && !self.spaced() {
+                    self.next();
+                    return Ok(math_type::exponential(self.parse_attachment()?));
+                }
+
+                if *self.peek() == Token::Symbol('(') && !self.spaced() {
+                    let parameter = self.parse_list()?;
+                    return build_function(&name, parameter, position);
+                }
+
+                if name == "Undefined" {
+                    return Ok(MathType::new(MathTypeName::Undefined, vec![]));
+                }
+
+                Ok(math_type::variable(&name))
+            },
+            Token::Text(text) => match text.as_str() {
+                typst_symbols::EMPTY_SUM => Ok(math_type::sum(vec![])),
+                typst_symbols::EMPTY_PRODUCT => Ok(math_type::product(vec![])),
+                _ => Err(ParseError::new(position, &format!("Unsupported text \"{}\"", text))),
+            },
+            Token::Symbol('(') => {
+                let inner = self.parse_sum()?;
+                self.expect(')')?;
+                Ok(inner)
+            },
+            Token::End => Err(ParseError::new(position, "Unexpected end of input")),
+            Token::Symbol(c) => Err(ParseError::new(position, &format!("Unexpected '{}'", c))),
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<MathType>, ParseError> {
+        self.expect('(')?;
+
+        let mut list = vec![];
+        if *self.peek() == Token::Symbol(')') {
+            self.next();
+            return Ok(list);
+        }
+
+        loop {
+            list.push(self.parse_sum()?);
+
+            match self.peek() {
+                Token::Symbol(',') => {
+                    self.next();
+                },
+                Token::Symbol(')') => {
+                    self.next();
+                    return Ok(list);
+                },
+                _ => return Err(ParseError::new(self.position(), "Expected ',' or ')'")),
+            }
+        }
+    }
+
+    fn finish(&self) -> Result<(), ParseError> {
+        if *self.peek() != Token::End {
+            return Err(ParseError::new(self.position(), "Unexpected trailing input"));
+        }
+
+        Ok(())
+    }
+}
+
+fn build_function(name: &str, mut parameter: Vec<MathType>, position: usize) -> Result<MathType, ParseError> {
+    let type_name = match name {
+        typst_symbols::EXPONENTIAL => MathTypeName::Exp,
+        typst_symbols::LOGARITHM => MathTypeName::LogN,
+        typst_symbols::FRACTION => {
+            if parameter.len() != 2 {
+                return Err(ParseError::new(position, &format!("'{}' takes 2 parameters, found {}", name, parameter.len())));
+            }
+
+            let denominator = parameter.pop().unwrap();
+            let numerator = parameter.pop().unwrap();
+
+            return Ok(build_fraction(numerator, denominator));
+        },
+        _ => MathTypeName::Function(name.to_string()),
+    };
+
+    MathType::try_new(type_name, parameter).map_err(|error| ParseError::new(position, &error.to_string()))
+}
+
+/*
+Shared by a / b and frac(a, b), the inverse of how get_typst_string prints negative exponents:
+frac(1, 2) => rational(1, 2)
+frac(1, x) => pow(x, -(1))
+frac(a b, c x^2) => *(a, b, pow(c, -(1)), pow(x, -(2)))
+ */
+fn build_fraction(numerator: MathType, denominator: MathType) -> MathType {
+    if let (MathTypeName::NaturalNumber(a), MathTypeName::NaturalNumber(b)) = (&numerator.type_name, &denominator.type_name) {
+        if let (Ok(a), Ok(b)) = (i128::try_from(*a), i128::try_from(*b)) {
+            if let Ok(value) = Rational::try_new(a, b) {
+                return math_type::rational(value);
+            }
+        }
+    }
+
+    let mut factors = match numerator.type_name {
+        MathTypeName::Product => numerator.parameter,
+        MathTypeName::NaturalNumber(1) => vec![],
+        _ => vec![numerator],
+    };
+
+    let denominators = match denominator.type_name {
+        MathTypeName::Product => denominator.parameter,
+        _ => vec![denominator],
+    };
+
+    for factor in denominators {
+        let inverted = match factor.type_name {
+            MathTypeName::Power if factor.parameter[1].constant_value().is_some() => {
+                let mut parameter = factor.parameter;
+                let exponent = parameter.pop().unwrap();
+                math_type::power(parameter.pop().unwrap(), math_type::flip_sign(exponent))
+            },
+            _ => math_type::reciprocal(factor),
+        };
+
+        factors.push(inverted);
+    }
+
+    if factors.len() == 1 {
+        return factors.remove(0);
+    }
+
+    math_type::product(factors)
+}
+
+//Blanks out surrounding $ delimiters so error positions still refer to the input
+fn strip_delimiters(input: &str) -> String {
+    let trimmed = input.trim();
+    if trimmed.len() >= 2 && trimmed.starts_with('$') && trimmed.ends_with('$') {
+        let start = input.find('$').unwrap();
+        let end = input.rfind('$').unwrap();
+        return format!("{} {} {}", &input[..start], &input[start + 1..end], &input[end + 1..]);
+    }
+
+    input.to_string()
+}
+
+impl MathType {
+    /*
+    x_i theta^1_(i 1) => *(x_i, theta^1_(i 1))
+    sum_(i)^(M) x_i => sum_(i)^(M)(x_i)
+    e^(x) => exp(x)
+    frac(a, b), a / b => *(a, pow(b, -(1)))
+    frac(1, b), 1 / b => pow(b, -(1))
+    phi'(x) => phi'(x)
+     */
+    pub fn parse_typst(input: &str) -> Result<MathType, ParseError> {
+        let mut parser = Parser {
+            lexemes: tokenize(&strip_delimiters(input))?,
+            index: 0,
+        };
+
+        let result = parser.parse_sum()?;
+        parser.finish()?;
+
+        Ok(result)
+    }
+
+    //Parses lhs = rhs, as used for the definitions in notes/paper.typ
+    pub fn parse_typst_equation(input: &str) -> Result<(MathType, MathType), ParseError> {
+        let mut parser = Parser {
+            lexemes: tokenize(&strip_delimiters(input))?,
+            index: 0,
+        };
+
+        let left = parser.parse_sum()?;
+        parser.expect('=')?;
+        let right = parser.parse_sum()?;
+        parser.finish()?;
+
+        Ok((left, right))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> MathType {
+        MathType::parse_typst(input).unwrap_or_else(|error| panic!("{} in {}", error, input))
+    }
+
+    fn round_trip(tree: MathType) {
+        let printed = tree.get_typst_string();
+        let reparsed = parse(&printed);
+
+        assert!(tree == reparsed, "{} => {} => {}", tree.get_string(), printed, reparsed.get_string());
+    }
+
+    #[test]
+    fn power_accepts_whitespace_and_sign() {
+        let x = math_type::variable("x");
+
+        assert!(parse("x^ 2") == math_type::power(x.clone(), math_type::natural_number(2)));
+        assert!(parse("x^(-1)") == math_type::reciprocal(x.clone()));
+        assert!(parse("x^-1") == math_type::reciprocal(x.clone()));
+        assert!(parse("x^-1 y") == math_type::product(vec![math_type::reciprocal(x), math_type::variable("y")]));
+    }
+
+    #[test]
+    fn big_operator_takes_a_group_as_argument() {
+        let sum = math_type::function("sum_(i)^(M)", vec![math_type::variable("x_i")]);
+
+        assert!(parse("sum_(i)^(M)(x_i) y") == math_type::product(vec![sum.clone(), math_type::variable("y")]));
+        assert!(parse("sum_(i)^(M) x_i y") == math_type::function("sum_(i)^(M)", vec![parse("x_i y")]));
+        round_trip(math_type::product(vec![sum, math_type::variable("y")]));
+    }
+
+    #[test]
+    fn slash_and_frac_agree() {
+        for (slash, frac) in [("1/2", "frac(1, 2)"), ("1/x", "frac(1, x)"), ("a/b", "frac(a, b)"), ("(a b) / (c d)", "frac(a b, c d)"), ("a b / c", "a frac(b, c)"), ("a/b/c", "frac(a, b c)")] {
+            assert!(parse(slash) == parse(frac), "{} != {}", slash, frac);
+        }
+
+        assert!(parse("frac(1, x)") == math_type::reciprocal(math_type::variable("x")));
+        assert!(parse("1/2") == math_type::rational(Rational::new(1, 2)));
+    }
+
+    #[test]
+    fn round_trips_through_get_typst_string() {
+        let (a, b, x) = (math_type::variable("a"), math_type::variable("b"), math_type::variable("x"));
+
+        for tree in [
+            math_type::reciprocal(x.clone()),
+            math_type::fraction(a.clone(), b.clone()),
+            math_type::product(vec![a.clone(), b.clone(), math_type::reciprocal(x.clone())]),
+            math_type::product(vec![a.clone(), math_type::power(x.clone(), math_type::flip_sign(math_type::natural_number(2)))]),
+            math_type::sum(vec![math_type::exponential(x.clone()), math_type::logarithm(a.clone()), math_type::rational(Rational::new(3, 4))]),
+            math_type::function("f'", vec![math_type::sum(vec![a.clone(), b.clone()])]),
+            math_type::power(math_type::sum(vec![a.clone(), b.clone()]), math_type::natural_number(2)),
+            math_type::flip_sign(math_type::sum(vec![a.clone(), b.clone()])),
+            math_type::sum(vec![a.clone(), math_type::flip_sign(math_type::sum(vec![b.clone(), x.clone()]))]),
+            math_type::flip_sign(math_type::power(math_type::sum(vec![a, b]), math_type::natural_number(2))),
+            math_type::sum(vec![]),
+            math_type::product(vec![]),
+        ] {
+            round_trip(tree);
+        }
+    }
+}
@@ -1 +1,14 @@
-pub const PARTIAL_DERIVATIVE: &str = "sym.partial";
\ No newline at end of file
+pub const PARTIAL_DERIVATIVE: &str = "sym.partial";
+
+//Typst names with a fixed meaning in math markup
+pub const BIG_OPERATORS: [&str; 3] = ["sum", "product", "integral"];
+pub const MULTIPLICATION: [&str; 4] = ["dot", "dot.op", "times", "ast"];
+
+pub const EULER: &str = "e";
+pub const FRACTION: &str = "frac";
+pub const EXPONENTIAL: &str = "exp";
+pub const LOGARITHM: &str = "ln";
+
+//Text used by get_typst_string for empty sums and products
+pub const EMPTY_SUM: &str = "zero";
+pub const EMPTY_PRODUCT: &str = "one";